@@ -5,58 +5,182 @@ layout(std430, binding = 3) buffer layoutName
     uint grid[];
 };
 
+// Extra per cell flags, see the OVERLAY_ bits
+layout(std430, binding = 4) buffer overlayLayout
+{
+    uint overlay[];
+};
+
+// Toolbar pixels, red in the lowest byte, row by row across the window
+layout(std430, binding = 5) buffer toolbarLayout
+{
+    uint toolbar[];
+};
+
 layout(pixel_center_integer) in vec4 gl_FragCoord;
 out vec4 colorOut;
 
 uniform int grid_width;
+uniform int win_width = 2400;
 uniform int win_height = 1400;
+// Rows of pixels at the top of the window taken by the toolbar
+uniform int toolbar_height = 0;
+// Size of a cell on screen in pixels, including camera zoom
 uniform float scale = 14.0;
 uniform int max_fill = 64;
 
+// Palette colours and shade variation, indexed by particle type id
+uniform vec3 colors[4];
+uniform float variations[4];
+
+// See RenderMode::uniform_id
+const int MODE_NORMAL = 0;
+const int MODE_FILL_RATIO = 1;
+const int MODE_CHANGED_CELLS = 2;
+const int MODE_TYPE_MASK = 3;
+
+// See the OVERLAY_ statics in render.rs
+const uint OVERLAY_CHANGED = 1u;
+const uint OVERLAY_PREVIEW = 2u;
+
+uniform int render_mode = MODE_NORMAL;
+// Particle type id shown in MODE_TYPE_MASK
+uniform int mask_type = 1;
+// Particle type id a tool is about to draw on OVERLAY_PREVIEW cells
+uniform int preview_type = 1;
+
+// Area of the window the grid is drawn in, in pixels. Anything outside of it
+// is letterboxing.
+uniform int view_x = 0;
+uniform int view_y = 0;
+uniform float view_width = 2400.0;
+uniform float view_height = 1400.0;
+
+// Grid coordinates shown at the top left of the view
+uniform float camera_x = 0.0;
+uniform float camera_y = 0.0;
+
 // In grid coordinates
 uniform int mouse_x = 4;
 uniform int mouse_y = 4;
 
-bool should_draw_box(float x, float y, int grid_x, int grid_y) {
-    // FIXME: Breaks for some scales
+// Grid line overlay. Only drawn once cells are at least this many pixels wide.
+uniform bool show_grid_lines = false;
+const float MIN_GRID_LINE_SCALE = 4.0;
+
+// in_cell is how far into its cell the pixel is, in pixels from the top left
+bool should_draw_box(vec2 in_cell, int grid_x, int grid_y) {
     // Hey Marvin, it's a box
-    return 
+    return
         (mouse_x == grid_x && mouse_y == grid_y) &&
-        ((x == grid_x * scale || x == (grid_x + 1) * scale - 1) ||
-         (y == grid_y * scale || y == (grid_y + 1) * scale - 1));
+        (any(lessThan(in_cell, vec2(1.0))) || any(greaterThanEqual(in_cell, vec2(scale - 1.0))));
 }
 
-void main() {
-    float x = gl_FragCoord.x;
-    float y = float(win_height) - gl_FragCoord.y;
+bool should_draw_grid_line(vec2 in_cell) {
+    return
+        show_grid_lines &&
+        scale >= MIN_GRID_LINE_SCALE &&
+        any(lessThan(in_cell, vec2(1.0)));
+}
 
-    int grid_x = int(x / scale);
-    int grid_y = int(y / scale);
+// Water goes from blue (nearly empty) through green to red (full). Everything
+// else is greyed out.
+vec4 fill_heat_color(uint p_type_id, float fill_percent) {
+    if (p_type_id == 0) {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    } else if (p_type_id != 1) {
+        return vec4(0.2, 0.2, 0.2, 1.0);
+    }
 
-    // Left edge
-    // grid_x * scale - 1
-    // Right edge
-    // grid_x * (scale + 1) + 1
+    vec3 cold = vec3(0.0, 0.0, 1.0);
+    vec3 warm = vec3(0.0, 1.0, 0.0);
+    vec3 hot = vec3(1.0, 0.0, 0.0);
 
-    if (should_draw_box(x, y, grid_x, grid_y)) {
-        colorOut = vec4(1.0, 1.0, 1.0, 1.0);
-        return;
-    }
+    vec3 heat = fill_percent < 0.5
+        ? mix(cold, warm, fill_percent * 2.0)
+        : mix(warm, hot, (fill_percent - 0.5) * 2.0);
 
-    uint val = grid[grid_x + grid_y * grid_width];
+    return vec4(heat, 1.0);
+}
+
+vec4 particle_color(uint index) {
+    uint val = grid[index];
 
+    uint shade      = (val >> 16) & 0xff;
     uint p_type_id  = (val >> 8) & 0xff;
     uint fill_ratio = val & 0xff;
 
     float fill_percent = fill_ratio / float(max_fill);
 
-    if (p_type_id == 1) {
-        colorOut = fill_percent * vec4(0.0, 0.0, 1.0, 1.0);
-    } else if (p_type_id == 2) {
-        colorOut = fill_percent * vec4(194.0/255, 178.0/255, 128.0/255, 1.0);
-    } else if (p_type_id == 3) {
-        colorOut = fill_percent * vec4(42.0/255, 23.0/255, 11.0/255, 1.0);
+    if (render_mode == MODE_FILL_RATIO) {
+        return fill_heat_color(p_type_id, fill_percent);
+    } else if (render_mode == MODE_TYPE_MASK) {
+        return p_type_id == mask_type ? vec4(1.0) : vec4(0.0, 0.0, 0.0, 1.0);
+    }
+
+    vec4 color;
+
+    if (p_type_id == 0) {
+        color = vec4(colors[0], 1.0);
     } else {
+        // Keep in sync with Palette::particle_color
+        float shade_factor = 1.0 + variations[p_type_id] * (shade / 255.0 - 0.5);
+
+        color = vec4(clamp(colors[p_type_id] * fill_percent * shade_factor, 0.0, 1.0), 1.0);
+    }
+
+    if (render_mode == MODE_CHANGED_CELLS) {
+        color = (overlay[index] & OVERLAY_CHANGED) != 0
+            ? vec4(1.0, 0.0, 1.0, 1.0)
+            : vec4(color.rgb * 0.3, 1.0);
+    }
+
+    if ((overlay[index] & OVERLAY_PREVIEW) != 0) {
+        color = vec4(mix(color.rgb, colors[preview_type], 0.6) + 0.15, 1.0);
+    }
+
+    return color;
+}
+
+vec4 toolbar_color(int x, int y) {
+    uint val = toolbar[x + y * win_width];
+
+    return vec4(
+        (val & 0xff) / 255.0,
+        ((val >> 8) & 0xff) / 255.0,
+        ((val >> 16) & 0xff) / 255.0,
+        1.0
+    );
+}
+
+void main() {
+    int win_y = win_height - 1 - int(gl_FragCoord.y);
+
+    if (win_y < toolbar_height) {
+        colorOut = toolbar_color(int(gl_FragCoord.x), win_y);
+        return;
+    }
+
+    float x = gl_FragCoord.x - view_x;
+    float y = float(win_height) - gl_FragCoord.y - view_y;
+
+    if (x < 0 || y < 0 || x >= view_width || y >= view_height) {
         colorOut = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec2 cell_pos = vec2(x, y) / scale + vec2(camera_x, camera_y);
+    // Working from the fractional part keeps edges one pixel wide at any scale
+    vec2 in_cell = fract(cell_pos) * scale;
+
+    int grid_x = int(floor(cell_pos.x));
+    int grid_y = int(floor(cell_pos.y));
+
+    if (should_draw_box(in_cell, grid_x, grid_y)) {
+        colorOut = vec4(1.0, 1.0, 1.0, 1.0);
+    } else if (should_draw_grid_line(in_cell)) {
+        colorOut = vec4(70.0/255, 70.0/255, 70.0/255, 1.0);
+    } else {
+        colorOut = particle_color(grid_x + grid_y * grid_width);
     }
 }
@@ -0,0 +1,195 @@
+use crate::save_state::SaveState;
+
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub static AUTOSAVE_DIR: &str = "autosaves";
+static AUTOSAVE_PREFIX: &str = "autosave-";
+static AUTOSAVE_SUFFIX: &str = ".mp";
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AutosaveInterval {
+    Seconds(f32),
+    Ticks(u64),
+    Off,
+}
+
+impl std::str::FromStr for AutosaveInterval {
+    type Err = String;
+
+    // "30s" or "30" for seconds, "600t" for physics ticks, or "off"
+    fn from_str(s: &str) -> Result<AutosaveInterval, String> {
+        let err = || format!("Bad autosave interval '{}', expected e.g. 30s, 600t or off", s);
+
+        // Anything not after some time would save every frame
+        if s == "off" {
+            Ok(AutosaveInterval::Off)
+        } else if let Some(ticks) = s.strip_suffix('t') {
+            ticks.parse().ok()
+                .filter(|ticks| *ticks > 0)
+                .map(AutosaveInterval::Ticks)
+                .ok_or_else(err)
+        } else {
+            s.trim_end_matches('s').parse::<f32>().ok()
+                .filter(|secs| secs.is_finite() && *secs > 0.0)
+                .map(AutosaveInterval::Seconds)
+                .ok_or_else(err)
+        }
+    }
+}
+
+// Periodically saves the world into a directory, usually AUTOSAVE_DIR,
+// keeping the newest few
+pub struct Autosaver {
+    dir: String,
+    interval: AutosaveInterval,
+    keep: usize,
+    last_save_time: f32,
+    last_save_tick: u64,
+}
+
+impl Autosaver {
+    pub fn new(dir: &str, interval: AutosaveInterval, keep: usize) -> Autosaver {
+        Autosaver {
+            dir: dir.to_string(),
+            interval,
+            keep,
+            last_save_time: 0.0,
+            last_save_tick: 0,
+        }
+    }
+
+    // Ticks can go backwards when the timeline is rewound
+    pub fn is_due(&self, curr_time: f32, curr_tick: u64) -> bool {
+        match self.interval {
+            AutosaveInterval::Seconds(secs) => curr_time - self.last_save_time >= secs,
+            AutosaveInterval::Ticks(ticks)  => curr_tick.saturating_sub(self.last_save_tick) >= ticks,
+            AutosaveInterval::Off           => false,
+        }
+    }
+
+    pub fn save(&mut self, state: &SaveState, curr_time: f32, curr_tick: u64) -> Result<String, String> {
+        self.last_save_time = curr_time;
+        self.last_save_tick = curr_tick;
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+
+        // Zero padded so the names sort by age
+        let path = format!("{}/{}{:015}{}", self.dir, AUTOSAVE_PREFIX, millis, AUTOSAVE_SUFFIX);
+        state.save_atomic(&path)?;
+
+        let autosaves = list_autosaves(&self.dir);
+        if autosaves.len() > self.keep {
+            for old in &autosaves[self.keep..] {
+                let _ = fs::remove_file(old);
+            }
+        }
+
+        Ok(path)
+    }
+}
+
+// Paths of all autosaves, newest first
+fn list_autosaves(dir: &str) -> Vec<String> {
+    let mut paths: Vec<String> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with(AUTOSAVE_PREFIX) && name.ends_with(AUTOSAVE_SUFFIX))
+            .map(|name| format!("{}/{}", dir, name))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    paths.sort();
+    paths.reverse();
+    paths
+}
+
+// The newest autosave that still loads, for --recover
+pub fn load_newest(dir: &str) -> Result<(String, SaveState), String> {
+    for path in list_autosaves(dir) {
+        match SaveState::load(path.clone()) {
+            Ok(state) => return Ok((path, state)),
+            Err(err)  => println!("Skipping autosave: {}", err),
+        }
+    }
+
+    Err(format!("No valid autosaves found in {}", dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{Particle, ParticleGrid, ParticleType};
+
+    // An empty directory of its own for each test
+    fn test_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("magicpixel-autosave-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir.to_string_lossy().to_string()
+    }
+
+    // A world told apart from the others by where its sand is
+    fn state(x: i32) -> SaveState {
+        let mut grid = ParticleGrid::new(10, 1);
+        grid.set(x, 0, Particle { p_type: ParticleType::Sand, fill_ratio: 8, shade: 0 });
+        SaveState { max_fill: 8, grid }
+    }
+
+    // Autosaves are named by the millisecond, so keep them apart
+    fn save(autosaver: &mut Autosaver, state: &SaveState) -> String {
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        autosaver.save(state, 0.0, 0).unwrap()
+    }
+
+    #[test]
+    fn parse_interval() {
+        assert_eq!("30s".parse(), Ok(AutosaveInterval::Seconds(30.0)));
+        assert_eq!("2.5".parse(), Ok(AutosaveInterval::Seconds(2.5)));
+        assert_eq!("600t".parse(), Ok(AutosaveInterval::Ticks(600)));
+        assert_eq!("off".parse(), Ok(AutosaveInterval::Off));
+
+        for bad in ["0", "0s", "0t", "-5", "-5t", "inf", "NaN", "soon"].iter() {
+            assert!(bad.parse::<AutosaveInterval>().is_err(), "{} was accepted", bad);
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest() {
+        let dir = test_dir("keep");
+        let mut autosaver = Autosaver::new(&dir, AutosaveInterval::Off, 2);
+
+        let paths: Vec<String> = (0..4).map(|x| save(&mut autosaver, &state(x))).collect();
+
+        assert_eq!(list_autosaves(&dir), vec![paths[3].clone(), paths[2].clone()]);
+
+        // Written to a temporary file first, which is renamed away
+        let files = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, 2);
+    }
+
+    #[test]
+    fn recover_skips_broken_autosaves() {
+        let dir = test_dir("recover");
+        let mut autosaver = Autosaver::new(&dir, AutosaveInterval::Off, 5);
+
+        save(&mut autosaver, &state(1));
+        let newest_valid = save(&mut autosaver, &state(2));
+        let broken = save(&mut autosaver, &state(3));
+        fs::write(&broken, [crate::save_state::SAVE_VERSION, 1, 2, 3]).unwrap();
+
+        let recovered = load_newest(&dir);
+        let nothing = load_newest(&test_dir("recover-empty"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let (path, recovered) = recovered.unwrap();
+        assert_eq!(path, newest_valid);
+        assert_eq!(recovered.grid, state(2).grid);
+        assert!(nothing.is_err());
+    }
+}
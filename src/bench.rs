@@ -0,0 +1,117 @@
+// Timings of Physics::update on the canonical scenes, which can be saved and
+// compared against a run from another commit
+
+use crate::config;
+use crate::physics::Physics;
+use crate::scenes::Scene;
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+// Same scenes, same seed, same work on every run
+static BENCH_SEED: u64 = 0;
+static BENCH_MAX_FILL: u8 = 8;
+
+pub struct BenchResult {
+    pub scene: Scene,
+    // Width and height of the square grid
+    pub size: i32,
+    pub ticks_per_sec: f64,
+    pub ns_per_cell: f64,
+}
+
+impl BenchResult {
+    // How results are matched up when comparing, e.g. "sand_pile/128"
+    fn key(&self) -> String {
+        format!("{}/{}", self.scene.name(), self.size)
+    }
+}
+
+// Times `ticks` updates from a freshly built scene, keeping the fastest of
+// `runs` tries since slower runs are mostly noise from the rest of the system
+pub fn run(scene: Scene, size: i32, ticks: u64, runs: u32) -> BenchResult {
+    let mut best = f64::MAX;
+
+    for _ in 0..runs.max(1) {
+        let grid = scene.build(size, size, BENCH_MAX_FILL, BENCH_SEED);
+        let mut physics = Physics::with_seed(grid, BENCH_MAX_FILL, BENCH_SEED);
+
+        let start = Instant::now();
+
+        for _ in 0..ticks {
+            physics.update();
+        }
+
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+
+    let cells = size as f64 * size as f64;
+
+    BenchResult {
+        scene,
+        size,
+        ticks_per_sec: ticks as f64 / best,
+        ns_per_cell: best * 1e9 / (ticks as f64 * cells),
+    }
+}
+
+// One "<scene>/<size> = <ns per cell>" line per result
+pub fn save_results(path: &str, results: &[BenchResult]) -> Result<(), String> {
+    let mut contents = String::from("# ns per cell per tick, from `magicpixel bench`\n");
+
+    for result in results {
+        contents.push_str(&format!("{} = {:.4}\n", result.key(), result.ns_per_cell));
+    }
+
+    std::fs::write(path, contents).map_err(|e| format!("Could not write {}: {}", path, e))
+}
+
+pub fn load_results(path: &str) -> Result<HashMap<String, f64>, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path, e))?;
+
+    let mut results = HashMap::new();
+
+    for (line_no, entry) in config::entries(&contents) {
+        let err = |msg: &str| format!("{}: line {}: {}", path, line_no, msg);
+
+        let (key, value) = entry.map_err(|e| err(&e))?;
+        let ns_per_cell = value.parse::<f64>().map_err(|_| err("expected a number"))?;
+
+        results.insert(key.to_string(), ns_per_cell);
+    }
+
+    Ok(results)
+}
+
+pub fn print_header(baseline: Option<&HashMap<String, f64>>) {
+    print!("{:<12}{:>6}{:>12}{:>10}", "scene", "size", "ticks/s", "ns/cell");
+
+    if baseline.is_some() {
+        print!("{:>10}{:>9}", "before", "change");
+    }
+
+    println!();
+}
+
+// Positive changes are slower
+pub fn print_result(result: &BenchResult, baseline: Option<&HashMap<String, f64>>) {
+    print!(
+        "{:<12}{:>6}{:>12.1}{:>10.2}",
+        result.scene.name(),
+        result.size,
+        result.ticks_per_sec,
+        result.ns_per_cell
+    );
+
+    match baseline.map(|b| b.get(&result.key())) {
+        Some(Some(before)) => {
+            let change = (result.ns_per_cell / before - 1.0) * 100.0;
+            print!("{:>10.2}{:>+8.1}%", before, change);
+        },
+        Some(None) => print!("{:>10}{:>9}", "-", "new"),
+        None       => {},
+    }
+
+    println!();
+}
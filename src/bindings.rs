@@ -0,0 +1,467 @@
+use crate::config;
+
+use sdl2::keyboard::{Keycode, Mod};
+
+use std::path::Path;
+
+pub static DEFAULT_BINDINGS_PATH: &str = "keybindings.cfg";
+
+// Everything the keyboard can do in the main loop
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    Quit,
+    TogglePause,
+    Save,
+    SaveAs,
+    SaveSlot,
+    LoadSlot,
+    NextSlot,
+    Step,
+    StepMany,
+    Faster,
+    Slower,
+    RewindTick,
+    ForwardTick,
+    RewindFar,
+    ForwardFar,
+    ToggleExport,
+    NextMaterial,
+    NextTool,
+    BrushBigger,
+    BrushSmaller,
+    Copy,
+    Cut,
+    Paste,
+    RotateClipboard,
+    MirrorClipboard,
+    FlipClipboard,
+    SaveStamp,
+    NextStamp,
+    GrowWorld,
+    ShrinkWorld,
+    ToggleGridLines,
+    RenderNormal,
+    RenderFillRatio,
+    RenderChangedCells,
+    MaskWater,
+    MaskSand,
+    MaskWood,
+    ToggleHelp,
+}
+
+impl Action {
+    pub const ALL: [Action; 38] = [
+        Action::Quit,
+        Action::TogglePause,
+        Action::Save,
+        Action::SaveAs,
+        Action::SaveSlot,
+        Action::LoadSlot,
+        Action::NextSlot,
+        Action::Step,
+        Action::StepMany,
+        Action::Faster,
+        Action::Slower,
+        Action::RewindTick,
+        Action::ForwardTick,
+        Action::RewindFar,
+        Action::ForwardFar,
+        Action::ToggleExport,
+        Action::NextMaterial,
+        Action::NextTool,
+        Action::BrushBigger,
+        Action::BrushSmaller,
+        Action::Copy,
+        Action::Cut,
+        Action::Paste,
+        Action::RotateClipboard,
+        Action::MirrorClipboard,
+        Action::FlipClipboard,
+        Action::SaveStamp,
+        Action::NextStamp,
+        Action::GrowWorld,
+        Action::ShrinkWorld,
+        Action::ToggleGridLines,
+        Action::RenderNormal,
+        Action::RenderFillRatio,
+        Action::RenderChangedCells,
+        Action::MaskWater,
+        Action::MaskSand,
+        Action::MaskWood,
+        Action::ToggleHelp,
+    ];
+
+    // Name used in the bindings file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit               => "quit",
+            Action::TogglePause        => "pause",
+            Action::Save               => "save",
+            Action::SaveAs             => "save_as",
+            Action::SaveSlot           => "save_slot",
+            Action::LoadSlot           => "load_slot",
+            Action::NextSlot           => "next_slot",
+            Action::Step               => "step",
+            Action::StepMany           => "step_many",
+            Action::Faster             => "faster",
+            Action::Slower             => "slower",
+            Action::RewindTick         => "rewind",
+            Action::ForwardTick        => "forward",
+            Action::RewindFar          => "rewind_far",
+            Action::ForwardFar         => "forward_far",
+            Action::ToggleExport       => "export",
+            Action::NextMaterial       => "next_material",
+            Action::NextTool           => "next_tool",
+            Action::BrushBigger        => "brush_bigger",
+            Action::BrushSmaller       => "brush_smaller",
+            Action::Copy               => "copy",
+            Action::Cut                => "cut",
+            Action::Paste              => "paste",
+            Action::RotateClipboard    => "rotate_clipboard",
+            Action::MirrorClipboard    => "mirror_clipboard",
+            Action::FlipClipboard      => "flip_clipboard",
+            Action::SaveStamp          => "save_stamp",
+            Action::NextStamp          => "next_stamp",
+            Action::GrowWorld          => "grow_world",
+            Action::ShrinkWorld        => "shrink_world",
+            Action::ToggleGridLines    => "grid_lines",
+            Action::RenderNormal       => "render_normal",
+            Action::RenderFillRatio    => "render_fill_ratio",
+            Action::RenderChangedCells => "render_changed_cells",
+            Action::MaskWater          => "mask_water",
+            Action::MaskSand           => "mask_sand",
+            Action::MaskWood           => "mask_wood",
+            Action::ToggleHelp         => "help",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Quit               => "Quit",
+            Action::TogglePause        => "Pause/resume",
+            Action::Save               => "Save",
+            Action::SaveAs             => "Save timestamped",
+            Action::SaveSlot           => "Save to slot",
+            Action::LoadSlot           => "Load slot",
+            Action::NextSlot           => "Next slot",
+            Action::Step               => "Step one tick",
+            Action::StepMany           => "Step many ticks",
+            Action::Faster             => "Speed up",
+            Action::Slower             => "Slow down",
+            Action::RewindTick         => "Back one tick",
+            Action::ForwardTick        => "Forward one tick",
+            Action::RewindFar          => "Back 10 ticks",
+            Action::ForwardFar         => "Forward 10 ticks",
+            Action::ToggleExport       => "Start/stop GIF export",
+            Action::NextMaterial       => "Next material",
+            Action::NextTool           => "Next tool",
+            Action::BrushBigger        => "Bigger brush",
+            Action::BrushSmaller       => "Smaller brush",
+            Action::Copy               => "Copy selection",
+            Action::Cut                => "Cut selection",
+            Action::Paste              => "Paste",
+            Action::RotateClipboard    => "Rotate paste",
+            Action::MirrorClipboard    => "Mirror paste",
+            Action::FlipClipboard      => "Flip paste",
+            Action::SaveStamp          => "Save paste as stamp",
+            Action::NextStamp          => "Paste next stamp",
+            Action::GrowWorld          => "Grow world",
+            Action::ShrinkWorld        => "Shrink world",
+            Action::ToggleGridLines    => "Grid lines",
+            Action::RenderNormal       => "Normal view",
+            Action::RenderFillRatio    => "Fill heat map",
+            Action::RenderChangedCells => "Changed cells",
+            Action::MaskWater          => "Water mask",
+            Action::MaskSand           => "Sand mask",
+            Action::MaskWood           => "Wood mask",
+            Action::ToggleHelp         => "This help",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().cloned().find(|a| a.name() == name)
+    }
+
+    fn default_keys(&self) -> &'static [&'static str] {
+        match self {
+            Action::Quit               => &["Escape"],
+            Action::TogglePause        => &["P"],
+            // Plain S is too easy to hit by accident
+            Action::Save               => &["Ctrl+S"],
+            Action::SaveAs             => &["Ctrl+Shift+S"],
+            Action::SaveSlot           => &["F7"],
+            Action::LoadSlot           => &["F8"],
+            Action::NextSlot           => &["Tab"],
+            Action::Step               => &["Space"],
+            Action::StepMany           => &["Shift+Space"],
+            Action::Faster             => &["PageUp"],
+            Action::Slower             => &["PageDown"],
+            Action::RewindTick         => &["Left"],
+            Action::ForwardTick        => &["Right"],
+            Action::RewindFar          => &["Shift+Left"],
+            Action::ForwardFar         => &["Shift+Right"],
+            Action::ToggleExport       => &["F9"],
+            Action::NextMaterial       => &[","],
+            Action::NextTool           => &["T"],
+            Action::BrushBigger        => &["="],
+            Action::BrushSmaller       => &["-"],
+            Action::Copy               => &["Ctrl+C"],
+            Action::Cut                => &["Ctrl+X"],
+            Action::Paste              => &["Ctrl+V"],
+            Action::RotateClipboard    => &["R"],
+            Action::MirrorClipboard    => &["M"],
+            Action::FlipClipboard      => &["Shift+M"],
+            Action::SaveStamp          => &["Ctrl+Shift+C"],
+            Action::NextStamp          => &["Ctrl+Shift+V"],
+            Action::GrowWorld          => &["]"],
+            Action::ShrinkWorld        => &["["],
+            Action::ToggleGridLines    => &["G"],
+            Action::RenderNormal       => &["F1"],
+            Action::RenderFillRatio    => &["F2"],
+            Action::RenderChangedCells => &["F3"],
+            Action::MaskWater          => &["F4"],
+            Action::MaskSand           => &["F5"],
+            Action::MaskWood           => &["F6"],
+            Action::ToggleHelp         => &["H"],
+        }
+    }
+}
+
+// A key plus the modifiers that have to be held with it
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct KeyBinding {
+    pub keycode: Keycode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl KeyBinding {
+    // Parses things like "S", "Ctrl+S" or "Shift+Alt+F2"
+    pub fn parse(s: &str) -> Result<KeyBinding, String> {
+        // The last part is the key. Allow binding '+' itself, e.g. "Ctrl++".
+        let (modifiers, key) = if s.ends_with("++") || s == "+" {
+            (&s[..s.len() - 1], "+")
+        } else {
+            match s.rfind('+') {
+                Some(i) => (&s[..i + 1], &s[i + 1..]),
+                None    => ("", s),
+            }
+        };
+
+        let keycode = Keycode::from_name(key.trim())
+            .ok_or_else(|| format!("unknown key '{}'", key))?;
+
+        let mut binding = KeyBinding { keycode, ctrl: false, shift: false, alt: false };
+
+        for modifier in modifiers.split('+').map(|m| m.trim()).filter(|m| !m.is_empty()) {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => binding.ctrl = true,
+                "shift"            => binding.shift = true,
+                "alt"              => binding.alt = true,
+                _ => return Err(format!("unknown modifier '{}'", modifier)),
+            }
+        }
+
+        Ok(binding)
+    }
+
+    // Modifiers have to match exactly, so "S" doesn't fire on Ctrl+S
+    pub fn matches(&self, keycode: Keycode, keymod: Mod) -> bool {
+        self.keycode == keycode &&
+            self.ctrl == keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) &&
+            self.shift == keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) &&
+            self.alt == keymod.intersects(Mod::LALTMOD | Mod::RALTMOD)
+    }
+}
+
+impl std::fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        write!(f, "{}", self.keycode.name())
+    }
+}
+
+pub struct KeyBindings {
+    bindings: Vec<(KeyBinding, Action)>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        let mut bindings = Vec::new();
+
+        for action in Action::ALL.iter() {
+            for key in action.default_keys() {
+                let binding = KeyBinding::parse(key).expect("Bad default key binding");
+                bindings.push((binding, *action));
+            }
+        }
+
+        KeyBindings { bindings }
+    }
+}
+
+impl KeyBindings {
+    // Load bindings from a file of "<action> = <key>[, <key>...]" lines.
+    // Actions that aren't listed keep their default keys. Without an explicit
+    // path, a missing default file just means the defaults.
+    pub fn load(path: Option<&str>) -> Result<KeyBindings, String> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_BINDINGS_PATH).is_file() => DEFAULT_BINDINGS_PATH,
+            None => return Ok(Default::default()),
+        };
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read key bindings {}: {}", path, e))?;
+
+        KeyBindings::parse(&contents).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(contents: &str) -> Result<KeyBindings, String> {
+        let mut bindings = KeyBindings::default();
+
+        for (line_no, entry) in config::entries(contents) {
+            let err = |msg: &str| format!("line {}: {}", line_no, msg);
+
+            let (name, keys) = entry.map_err(|e| err(&e))?;
+
+            let action = Action::from_name(name)
+                .ok_or_else(|| err(&format!("unknown action '{}'", name)))?;
+
+            bindings.bindings.retain(|(_, a)| *a != action);
+
+            for key in split_keys(keys) {
+                // "none" leaves the action unbound
+                if key.eq_ignore_ascii_case("none") {
+                    continue;
+                }
+
+                let binding = KeyBinding::parse(key).map_err(|e| err(&e))?;
+                bindings.bindings.push((binding, action));
+            }
+        }
+
+        Ok(bindings)
+    }
+
+    pub fn action_for(&self, keycode: Keycode, keymod: Mod) -> Option<Action> {
+        self.bindings.iter()
+            .find(|(binding, _)| binding.matches(keycode, keymod))
+            .map(|(_, action)| *action)
+    }
+
+    // One "keys: description" line per action, for the help overlay
+    pub fn help_lines(&self) -> Vec<String> {
+        Action::ALL.iter().map(|action| {
+            let keys: Vec<String> = self.bindings.iter()
+                .filter(|(_, a)| a == action)
+                .map(|(binding, _)| binding.to_string())
+                .collect();
+
+            let keys = if keys.is_empty() {
+                "unbound".to_string()
+            } else {
+                keys.join(", ")
+            };
+
+            format!("{:<10} {}", keys, action.description())
+        }).collect()
+    }
+}
+
+// Splits "<key>[, <key>...]". A comma can be a key too, so it only separates
+// keys when it isn't the start of one or right after a '+', e.g. "," or
+// "Ctrl+,, Shift+Tab".
+fn split_keys(keys: &str) -> Vec<&str> {
+    let mut split = Vec::new();
+    let mut rest = keys.trim();
+
+    while !rest.is_empty() {
+        let end = rest.char_indices()
+            .skip(1)
+            .find(|&(i, c)| c == ',' && !rest[..i].trim_end().ends_with('+'))
+            .map_or(rest.len(), |(i, _)| i);
+
+        split.push(rest[..end].trim());
+        let after = &rest[end..];
+        rest = after.strip_prefix(',').unwrap_or(after).trim_start();
+    }
+
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(keycode: Keycode, ctrl: bool, shift: bool, alt: bool) -> KeyBinding {
+        KeyBinding { keycode, ctrl, shift, alt }
+    }
+
+    fn keys_for(bindings: &KeyBindings, action: Action) -> Vec<KeyBinding> {
+        bindings.bindings.iter()
+            .filter(|(_, a)| *a == action)
+            .map(|(binding, _)| *binding)
+            .collect()
+    }
+
+    #[test]
+    fn parse_key_binding() {
+        assert_eq!(KeyBinding::parse("S"), Ok(binding(Keycode::S, false, false, false)));
+        assert_eq!(KeyBinding::parse("Ctrl+S"), Ok(binding(Keycode::S, true, false, false)));
+        assert_eq!(KeyBinding::parse("Shift+Alt+F2"), Ok(binding(Keycode::F2, false, true, true)));
+        assert_eq!(KeyBinding::parse("Ctrl++"), Ok(binding(Keycode::Plus, true, false, false)));
+        assert_eq!(KeyBinding::parse(","), Ok(binding(Keycode::Comma, false, false, false)));
+        assert_eq!(KeyBinding::parse("Ctrl+,"), Ok(binding(Keycode::Comma, true, false, false)));
+
+        assert!(KeyBinding::parse("").is_err());
+        assert!(KeyBinding::parse("Nope").is_err());
+        assert!(KeyBinding::parse("Super+S").is_err());
+    }
+
+    #[test]
+    fn split_comma_keys() {
+        assert_eq!(split_keys(","), vec![","]);
+        assert_eq!(split_keys("A, B"), vec!["A", "B"]);
+        assert_eq!(split_keys("A, ,"), vec!["A", ","]);
+        assert_eq!(split_keys(",, A"), vec![",", "A"]);
+        assert_eq!(split_keys("Ctrl+,, Tab"), vec!["Ctrl+,", "Tab"]);
+    }
+
+    #[test]
+    fn parse_key_bindings() {
+        let bindings = KeyBindings::parse("# Comment\n\nquit = Q, Ctrl+W\nhelp = none\n").unwrap();
+
+        assert_eq!(keys_for(&bindings, Action::Quit), vec![
+            binding(Keycode::Q, false, false, false),
+            binding(Keycode::W, true, false, false),
+        ]);
+        assert!(keys_for(&bindings, Action::ToggleHelp).is_empty());
+        // Unlisted actions keep their defaults
+        assert_eq!(keys_for(&bindings, Action::TogglePause), vec![binding(Keycode::P, false, false, false)]);
+
+        assert!(matches!(KeyBindings::parse("jump = Space"), Err(e) if e.contains("line 1")));
+        assert!(KeyBindings::parse("quit = Nope").is_err());
+        assert!(KeyBindings::parse("quit").is_err());
+    }
+
+    #[test]
+    fn parse_example_file() {
+        let bindings = KeyBindings::parse(include_str!("../assets/keybindings.example.cfg")).unwrap();
+
+        assert_eq!(keys_for(&bindings, Action::NextMaterial), vec![binding(Keycode::Comma, false, false, false)]);
+
+        for action in Action::ALL.iter() {
+            assert!(!keys_for(&bindings, *action).is_empty(), "{:?} is unbound", action);
+        }
+    }
+}
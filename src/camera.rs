@@ -0,0 +1,80 @@
+static MIN_ZOOM: f32 = 1.0;
+static MAX_ZOOM: f32 = 64.0;
+
+// Which part of the grid is visible. At zoom 1 the whole grid fits the window.
+pub struct Camera {
+    pub zoom: f32,
+    // Grid coordinates shown at the top left corner of the window
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Camera {
+            zoom: MIN_ZOOM,
+            x: 0.0,
+            y: 0.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Camera::new();
+    }
+
+    // Size of a single cell on screen, in pixels
+    pub fn cell_size(&self, scale: f32) -> f32 {
+        scale * self.zoom
+    }
+
+    pub fn screen_to_grid(&self, scale: f32, screen_x: i32, screen_y: i32) -> (i32, i32) {
+        let cell_size = self.cell_size(scale);
+
+        (
+            (screen_x as f32 / cell_size + self.x).floor() as i32,
+            (screen_y as f32 / cell_size + self.y).floor() as i32,
+        )
+    }
+
+    // Zoom by the given factor, keeping the point under the cursor in place
+    pub fn zoom_at(
+        &mut self,
+        factor: f32,
+        scale: f32,
+        screen_x: i32,
+        screen_y: i32,
+        grid_width: i32,
+        grid_height: i32
+    ) {
+        let old_cell_size = self.cell_size(scale);
+        let focus_x = screen_x as f32 / old_cell_size + self.x;
+        let focus_y = screen_y as f32 / old_cell_size + self.y;
+
+        self.zoom = (self.zoom * factor).max(MIN_ZOOM).min(MAX_ZOOM);
+
+        let new_cell_size = self.cell_size(scale);
+        self.x = focus_x - screen_x as f32 / new_cell_size;
+        self.y = focus_y - screen_y as f32 / new_cell_size;
+
+        self.clamp(grid_width, grid_height);
+    }
+
+    // Move the view by a distance in screen pixels
+    pub fn pan(&mut self, dx: i32, dy: i32, scale: f32, grid_width: i32, grid_height: i32) {
+        let cell_size = self.cell_size(scale);
+
+        self.x -= dx as f32 / cell_size;
+        self.y -= dy as f32 / cell_size;
+
+        self.clamp(grid_width, grid_height);
+    }
+
+    // Keep the view inside the grid
+    fn clamp(&mut self, grid_width: i32, grid_height: i32) {
+        let visible_width = grid_width as f32 / self.zoom;
+        let visible_height = grid_height as f32 / self.zoom;
+
+        self.x = self.x.max(0.0).min(grid_width as f32 - visible_width);
+        self.y = self.y.max(0.0).min(grid_height as f32 - visible_height);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::autosave::{self, AutosaveInterval};
+use crate::generator::{Generator, Preset};
+use crate::grid::{Anchor, ParticleGrid};
+use crate::save_state::SaveState;
+
+use clap::{AppSettings, Clap};
+
+#[derive(Clap)]
+#[clap(setting = AppSettings::ColoredHelp)]
+pub struct Opts {
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Clap)]
+pub enum Command {
+    /// Open the simulation in a window
+    Run(RunOpts),
+    /// Simulate a number of ticks without a window
+    Sim(SimOpts),
+    /// Convert a save to the current version, or between saves and PNGs
+    Convert(ConvertOpts),
+    /// Print a save's dimensions, version and material counts
+    Info(InfoOpts),
+    /// Time physics updates on the standard scenes, or on a given world
+    Bench(BenchOpts),
+    /// Replay a session recorded with `run --record-input`, and check it
+    /// ends with the recorded world
+    Replay(ReplayOpts),
+}
+
+// Where a simulation starts from
+#[derive(Clap)]
+pub struct WorldOpts {
+    /// Save file to start from
+    #[clap(short = 'l', long, conflicts_with_all = &["recover", "generate"])]
+    pub load: Option<String>,
+    /// Start from the newest autosave that loads
+    #[clap(long, conflicts_with = "generate")]
+    pub recover: bool,
+    /// Generate a new world: hills, lakes, caves, platforms or mixed
+    #[clap(short = 'g', long)]
+    pub generate: Option<Preset>,
+    /// Seed for --generate, the same seed and size give the same world
+    #[clap(long, default_value = "0")]
+    pub world_seed: u64,
+    /// Width of a new world, or to resize a loaded one to
+    #[clap(long)]
+    pub width: Option<i32>,
+    /// Height of a new world, or to resize a loaded one to
+    #[clap(long)]
+    pub height: Option<i32>,
+    /// Edge kept in place when resizing a loaded world, e.g. "bottom-center"
+    #[clap(short = 'a', long, default_value = "bottom-center")]
+    pub anchor: Anchor,
+    /// Fill levels per cell, for new worlds
+    #[clap(short = 'f', long, default_value = "8")]
+    pub max_fill: u8,
+}
+
+impl WorldOpts {
+    // Whether any of the options say where to start from
+    pub fn is_given(&self) -> bool {
+        self.load.is_some() || self.recover || self.generate.is_some() || self.width.is_some() || self.height.is_some()
+    }
+
+    // The starting world and its max fill
+    pub fn load_world(&self) -> Result<(ParticleGrid, u8), String> {
+        for size in [self.width, self.height].iter().flatten() {
+            if *size < 1 {
+                return Err(format!("World size must be positive, got {}", size));
+            }
+        }
+
+        if self.max_fill < 1 {
+            return Err("--max-fill must be at least 1".to_string());
+        }
+
+        let loaded_state = if self.recover {
+            let (path, state) = autosave::load_newest(autosave::AUTOSAVE_DIR)?;
+            println!("Recovered {}", path);
+            Some(state)
+        } else {
+            match &self.load {
+                Some(path) => Some(SaveState::load(path.clone())?),
+                None       => None,
+            }
+        };
+
+        match (loaded_state, self.width, self.height) {
+            (Some(state), width, height) => {
+                let grid = state.grid.resized(
+                    width.unwrap_or(state.grid.width),
+                    height.unwrap_or(state.grid.height),
+                    self.anchor
+                );
+                Ok((grid, state.max_fill))
+            },
+            (None, Some(width), Some(height)) => {
+                let grid = match self.generate {
+                    Some(preset) => Generator::preset(preset, self.world_seed).generate(width, height, self.max_fill),
+                    None         => ParticleGrid::new(width, height),
+                };
+                Ok((grid, self.max_fill))
+            },
+            (None, _, _) if self.generate.is_some() => {
+                Err("--generate needs a --width and --height".to_string())
+            },
+            _ => Err("Must give a save to --load, --recover, or a --width and --height".to_string()),
+        }
+    }
+}
+
+#[derive(Clap)]
+#[clap(setting = AppSettings::ColoredHelp)]
+pub struct RunOpts {
+    #[clap(flatten)]
+    pub world: WorldOpts,
+    /// Where the save key writes to. Defaults to the loaded save, or save.mp.
+    #[clap(short = 's', long)]
+    pub save_to: Option<String>,
+    /// Palette file, or the name of a preset in assets/palettes (e.g. "okabe-ito")
+    #[clap(short = 'p', long)]
+    pub palette: Option<String>,
+    /// Key bindings file. Defaults to keybindings.cfg if it exists.
+    #[clap(short = 'b', long)]
+    pub bindings: Option<String>,
+    /// How often to autosave: seconds ("30s"), physics ticks ("600t") or "off"
+    #[clap(long, default_value = "60s")]
+    pub autosave_interval: AutosaveInterval,
+    /// Number of autosaves to keep
+    #[clap(long, default_value = "5")]
+    pub autosave_keep: usize,
+    /// Ticks of history kept for rewinding, 0 to turn it off
+    #[clap(long, default_value = "600")]
+    pub history: u64,
+    /// Record every input to this file, written on quit
+    #[clap(long)]
+    pub record_input: Option<String>,
+    /// Export every Nth tick when exporting a GIF
+    #[clap(long, default_value = "2")]
+    pub export_every: u64,
+    /// Exported pixels per cell
+    #[clap(long, default_value = "2")]
+    pub export_scale: u32,
+    /// Seconds per tick at normal speed
+    #[clap(long, default_value = "0.05")]
+    pub tick_time: f32,
+    /// Ticks run by the step many key
+    #[clap(long, default_value = "10")]
+    pub step_ticks: u32,
+}
+
+#[derive(Clap)]
+#[clap(setting = AppSettings::ColoredHelp)]
+pub struct SimOpts {
+    #[clap(flatten)]
+    pub world: WorldOpts,
+    /// Ticks to simulate
+    #[clap(short = 't', long, default_value = "1000")]
+    pub ticks: u64,
+    /// Save the world as it is after the last tick
+    #[clap(short = 'o', long)]
+    pub out: Option<String>,
+    /// Seed for the physics, to get the same result every run
+    #[clap(long)]
+    pub seed: Option<u64>,
+    /// Export the simulation to a GIF, or a directory of PNGs
+    #[clap(short = 'r', long)]
+    pub record: Option<String>,
+    /// Export every Nth tick
+    #[clap(long, default_value = "2")]
+    pub export_every: u64,
+    /// Exported pixels per cell
+    #[clap(long, default_value = "2")]
+    pub export_scale: u32,
+    /// Palette for exports, a file or a preset name
+    #[clap(short = 'p', long)]
+    pub palette: Option<String>,
+}
+
+#[derive(Clap)]
+#[clap(setting = AppSettings::ColoredHelp)]
+pub struct ConvertOpts {
+    /// Save (any version) or PNG to read
+    pub input: String,
+    /// Where to write. Ending in .png writes an image, anything else a save.
+    pub output: String,
+    /// Pixels per cell in images
+    #[clap(long, default_value = "1")]
+    pub scale: u32,
+    /// Palette used to colour or read images, a file or a preset name
+    #[clap(short = 'p', long)]
+    pub palette: Option<String>,
+    /// Fill levels per cell, for saves made from images
+    #[clap(short = 'f', long, default_value = "8")]
+    pub max_fill: u8,
+}
+
+#[derive(Clap)]
+#[clap(setting = AppSettings::ColoredHelp)]
+pub struct InfoOpts {
+    pub path: String,
+}
+
+#[derive(Clap)]
+#[clap(setting = AppSettings::ColoredHelp)]
+pub struct BenchOpts {
+    /// Time this world instead of the standard scenes
+    #[clap(flatten)]
+    pub world: WorldOpts,
+    /// Ticks to time in each run
+    #[clap(short = 't', long, default_value = "200")]
+    pub ticks: u64,
+    /// Runs of each benchmark. The fastest is reported.
+    #[clap(long, default_value = "3")]
+    pub runs: u32,
+    /// Comma separated scenes to run: empty, sand_pile, dam_break,
+    /// full_basin, mixed, scattered. Defaults to all of them.
+    #[clap(long)]
+    pub scenes: Option<String>,
+    /// Comma separated widths of the square grids to run each scene at
+    #[clap(long, default_value = "64,128,256")]
+    pub sizes: String,
+    /// Write the results to a file, to compare against later
+    #[clap(long)]
+    pub save: Option<String>,
+    /// Compare against results saved with --save, e.g. from another commit
+    #[clap(long)]
+    pub compare: Option<String>,
+}
+
+#[derive(Clap)]
+#[clap(setting = AppSettings::ColoredHelp)]
+pub struct ReplayOpts {
+    pub path: String,
+    /// Save the world the replay ended with
+    #[clap(short = 'o', long)]
+    pub out: Option<String>,
+}
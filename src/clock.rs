@@ -0,0 +1,152 @@
+// Decides how many physics ticks each frame runs, at a chosen multiple of
+// the normal speed
+
+// Speed multipliers to step through. Infinite runs as many ticks as fit.
+static SPEEDS: [f32; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, f32::INFINITY];
+static NORMAL_SPEED_INDEX: usize = 2;
+
+pub struct TickClock {
+    // Seconds per tick at 1x
+    tick_time: f32,
+    speed_index: usize,
+    // Time of the last call to ticks_due or skip
+    last_time: Option<f32>,
+    // Ticks that have come due but haven't been run, including a fraction
+    // of the next one
+    owed: f32,
+}
+
+impl TickClock {
+    pub fn new(tick_time: f32) -> TickClock {
+        TickClock {
+            tick_time,
+            speed_index: NORMAL_SPEED_INDEX,
+            last_time: None,
+            owed: 0.0,
+        }
+    }
+
+    pub fn tick_time(&self) -> f32 {
+        self.tick_time
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        SPEEDS[self.speed_index].is_infinite()
+    }
+
+    // Seconds between ticks at the current speed, None when unlimited
+    pub fn tick_interval(&self) -> Option<f32> {
+        if self.is_unlimited() {
+            None
+        } else {
+            Some(self.tick_time / SPEEDS[self.speed_index])
+        }
+    }
+
+    pub fn faster(&mut self) {
+        self.speed_index = (self.speed_index + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed_index = self.speed_index.saturating_sub(1);
+    }
+
+    // e.g. "0.25x", "2x" or "max"
+    pub fn speed_label(&self) -> String {
+        if self.is_unlimited() {
+            "max".to_string()
+        } else {
+            format!("{}x", SPEEDS[self.speed_index])
+        }
+    }
+
+    // Ticks to run now, at most `max_ticks`. When more are due than that the
+    // rest are dropped, so a slow frame slows the simulation down rather than
+    // piling more work onto the frames after it. Unlimited speed always
+    // asks for `max_ticks`.
+    pub fn ticks_due(&mut self, time: f32, max_ticks: u32) -> u32 {
+        let elapsed = self.last_time.map_or(0.0, |last| (time - last).max(0.0));
+        self.last_time = Some(time);
+
+        let interval = match self.tick_interval() {
+            Some(interval) => interval,
+            None           => return max_ticks,
+        };
+
+        self.owed += elapsed / interval;
+        let due = self.owed.floor();
+
+        if due > max_ticks as f32 {
+            self.owed = 0.0;
+            max_ticks
+        } else {
+            self.owed -= due;
+            due as u32
+        }
+    }
+
+    // Let time pass without owing ticks for it, e.g. while paused
+    pub fn skip(&mut self, time: f32) {
+        self.last_time = Some(time);
+        self.owed = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Times in the tests are exact in binary, so ticks come due exactly
+
+    #[test]
+    fn ticks_follow_the_speed() {
+        let mut clock = TickClock::new(0.125);
+        assert_eq!(clock.ticks_due(0.0, 10), 0);
+        assert_eq!(clock.ticks_due(0.3125, 10), 2);
+        // The half tick left over carries on
+        assert_eq!(clock.ticks_due(0.375, 10), 1);
+
+        clock.faster();
+        assert_eq!(clock.speed_label(), "2x");
+        assert_eq!(clock.ticks_due(0.625, 10), 4);
+
+        clock.slower();
+        clock.slower();
+        clock.slower();
+        assert_eq!(clock.speed_label(), "0.25x");
+        assert_eq!(clock.ticks_due(1.125, 10), 1);
+    }
+
+    #[test]
+    fn slow_frames_are_capped() {
+        let mut clock = TickClock::new(0.125);
+        clock.ticks_due(0.0, 4);
+
+        // Ten seconds stuck in one frame doesn't become eighty ticks owed
+        assert_eq!(clock.ticks_due(10.0, 4), 4);
+        assert_eq!(clock.ticks_due(10.0625, 4), 0);
+    }
+
+    #[test]
+    fn skipped_time_is_not_owed() {
+        let mut clock = TickClock::new(0.125);
+        clock.ticks_due(0.0, 10);
+        clock.skip(5.0);
+
+        assert_eq!(clock.ticks_due(5.125, 10), 1);
+    }
+
+    #[test]
+    fn unlimited_runs_the_most() {
+        let mut clock = TickClock::new(0.125);
+
+        for _ in 0..10 {
+            clock.faster();
+        }
+
+        assert!(clock.is_unlimited());
+        assert_eq!(clock.speed_label(), "max");
+        assert_eq!(clock.tick_interval(), None);
+        assert_eq!(clock.ticks_due(0.0, 50), 50);
+    }
+}
@@ -0,0 +1,234 @@
+// Subcommands that run without a window
+
+use crate::TICK_TIME;
+use crate::bench;
+use crate::cli::{BenchOpts, ConvertOpts, InfoOpts, ReplayOpts, SimOpts};
+use crate::export::Exporter;
+use crate::grid::ParticleType;
+use crate::image;
+use crate::palette::Palette;
+use crate::physics::Physics;
+use crate::recording::Recording;
+use crate::save_state::{self, SaveState};
+use crate::scenes::Scene;
+
+use std::time::Instant;
+
+pub fn sim(opts: SimOpts) -> Result<(), String> {
+    let (grid, max_fill) = opts.world.load_world()?;
+
+    let mut physics = match opts.seed {
+        Some(seed) => Physics::with_seed(grid, max_fill, seed),
+        None       => Physics::new(grid, max_fill),
+    };
+
+    let mut exporter = match &opts.record {
+        Some(path) => {
+            let exporter = Exporter::create(path, physics.get_grid(), opts.export_every, opts.export_scale, TICK_TIME)?;
+            Some(exporter)
+        },
+        None => None,
+    };
+
+    let palette = load_palette(&opts.palette)?;
+
+    if let Some(exporter) = &mut exporter {
+        exporter.capture(physics.get_grid(), &palette, max_fill, 0)?;
+    }
+
+    let start = Instant::now();
+
+    for _ in 0..opts.ticks {
+        physics.update();
+
+        if let Some(exporter) = &mut exporter {
+            let tick = physics.get_tick();
+            exporter.capture(physics.get_grid(), &palette, max_fill, tick)?;
+        }
+    }
+
+    let secs = start.elapsed().as_secs_f32();
+    println!("Simulated {} ticks in {:.2}s", opts.ticks, secs);
+
+    if let Some(exporter) = exporter {
+        println!("Exported {} frames to {}", exporter.frames(), exporter.path());
+    }
+
+    if let Some(out) = opts.out {
+        let state = SaveState {
+            max_fill,
+            grid: physics.get_grid().as_ref().clone(),
+        };
+        state.save(out.clone())?;
+        println!("Saved {}", out);
+    }
+
+    Ok(())
+}
+
+pub fn convert(opts: ConvertOpts) -> Result<(), String> {
+    if opts.max_fill < 1 {
+        return Err("--max-fill must be at least 1".to_string());
+    }
+
+    let palette = load_palette(&opts.palette)?;
+
+    let state = if is_png(&opts.input) {
+        let (pixels, width, height) = image::read_png(&opts.input)?;
+
+        if width < opts.scale || height < opts.scale {
+            return Err(format!("{} is smaller than one cell at scale {}", opts.input, opts.scale));
+        }
+
+        SaveState {
+            max_fill: opts.max_fill,
+            grid: image::grid_from_rgb(&pixels, width, height, opts.scale, &palette, opts.max_fill),
+        }
+    } else {
+        SaveState::load(opts.input.clone())?
+    };
+
+    if is_png(&opts.output) {
+        let scale = opts.scale.max(1);
+        let pixels = image::render_rgb(&state.grid, &palette, state.max_fill, scale);
+        image::write_png(
+            &opts.output,
+            &pixels,
+            state.grid.width as u32 * scale,
+            state.grid.height as u32 * scale
+        )?;
+    } else {
+        state.save(opts.output.clone())?;
+    }
+
+    println!("Converted {} to {}", opts.input, opts.output);
+    Ok(())
+}
+
+pub fn info(opts: InfoOpts) -> Result<(), String> {
+    let (state, version) = SaveState::load_with_version(opts.path.clone())?;
+    let grid = &state.grid;
+
+    let upgrade_note = if version < save_state::SAVE_VERSION {
+        " (older version, upgraded when loaded)"
+    } else {
+        ""
+    };
+
+    println!("{}", opts.path);
+    println!("  version   {}{}", version, upgrade_note);
+    println!("  size      {}x{} ({} cells)", grid.width, grid.height, grid.grid.len());
+    println!("  max fill  {}", state.max_fill);
+
+    let counts = grid.material_counts();
+
+    println!("  {:<8}{:>10}{:>10}", "", "cells", "fill");
+    for p_type in ParticleType::ALL.iter() {
+        let id = p_type.id();
+        println!("  {:<8}{:>10}{:>10}", format!("{:?}", p_type), counts.cells[id], counts.fill[id]);
+    }
+
+    Ok(())
+}
+
+pub fn bench(opts: BenchOpts) -> Result<(), String> {
+    if opts.world.is_given() {
+        return bench_world(opts);
+    }
+
+    let scenes = match &opts.scenes {
+        Some(names) => names.split(',')
+            .map(|name| {
+                Scene::from_name(name.trim()).ok_or_else(|| {
+                    let valid: Vec<&str> = Scene::ALL.iter().map(|s| s.name()).collect();
+                    format!("Unknown scene '{}', expected one of {}", name, valid.join(", "))
+                })
+            })
+            .collect::<Result<Vec<Scene>, String>>()?,
+        None => Scene::ALL.to_vec(),
+    };
+
+    let sizes = opts.sizes.split(',')
+        .map(|size| match size.trim().parse::<i32>() {
+            Ok(size) if size > 0 => Ok(size),
+            _ => Err(format!("Bad size '{}', expected a positive number", size)),
+        })
+        .collect::<Result<Vec<i32>, String>>()?;
+
+    let baseline = match &opts.compare {
+        Some(path) => Some(bench::load_results(path)?),
+        None       => None,
+    };
+
+    bench::print_header(baseline.as_ref());
+
+    let mut results = Vec::new();
+
+    for scene in &scenes {
+        for size in &sizes {
+            let result = bench::run(*scene, *size, opts.ticks, opts.runs);
+            bench::print_result(&result, baseline.as_ref());
+            results.push(result);
+        }
+    }
+
+    if let Some(path) = &opts.save {
+        bench::save_results(path, &results)?;
+        println!("Saved results to {}", path);
+    }
+
+    Ok(())
+}
+
+fn bench_world(opts: BenchOpts) -> Result<(), String> {
+    let (grid, max_fill) = opts.world.load_world()?;
+    let cells = grid.grid.len();
+    let mut physics = Physics::with_seed(grid, max_fill, 0);
+
+    let start = Instant::now();
+
+    for _ in 0..opts.ticks {
+        physics.update();
+    }
+
+    let secs = start.elapsed().as_secs_f64();
+
+    println!("{} ticks in {:.3}s", opts.ticks, secs);
+    println!("{:.1} ticks/s, {:.2} ns/cell", opts.ticks as f64 / secs, secs * 1e9 / (opts.ticks as f64 * cells as f64));
+
+    Ok(())
+}
+
+pub fn replay(opts: ReplayOpts) -> Result<(), String> {
+    let recording = Recording::load(&opts.path)?;
+    let mut physics = recording.replay();
+
+    println!("Replayed {} inputs over {} ticks", recording.input_count(), recording.end_tick());
+
+    if let Some(out) = opts.out {
+        let state = SaveState {
+            max_fill: recording.max_fill(),
+            grid: physics.get_grid().as_ref().clone(),
+        };
+        state.save(out.clone())?;
+        println!("Saved {}", out);
+    }
+
+    if recording.matches_end(physics.get_grid()) {
+        println!("Replay matches the recorded session");
+        Ok(())
+    } else {
+        Err(format!("Replay of {} diverged from the recorded session", opts.path))
+    }
+}
+
+fn load_palette(name: &Option<String>) -> Result<Palette, String> {
+    match name {
+        Some(name) => Palette::load(name),
+        None       => Ok(Palette::default()),
+    }
+}
+
+fn is_png(path: &str) -> bool {
+    path.to_lowercase().ends_with(".png")
+}
@@ -0,0 +1,26 @@
+// Helpers for the small "key = value" text files used for palettes and key
+// bindings. Blank lines and lines starting with '#' are ignored.
+
+// Each meaningful line as (line number, key, value)
+pub fn entries(contents: &str) -> impl Iterator<Item = (usize, Result<(&str, &str), String>)> {
+    contents.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_no, line)| {
+            (line_no, split_pair(line).ok_or_else(|| "expected <key> = <value>".to_string()))
+        })
+}
+
+// Split "key = value" into its trimmed halves
+fn split_pair(line: &str) -> Option<(&str, &str)> {
+    let mut halves = line.splitn(2, '=');
+    let key = halves.next()?.trim();
+    let value = halves.next()?.trim();
+
+    if key.is_empty() || value.is_empty() {
+        None
+    } else {
+        Some((key, value))
+    }
+}
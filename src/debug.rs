@@ -7,17 +7,51 @@ use sdl2::ttf::Font;
 use sdl2::render::{Canvas};
 use sdl2::video::{Window};
 use sdl2::pixels::Color;
-use sdl2::render::TextureQuery;
+use sdl2::render::{Texture, TextureQuery};
+use sdl2::rect::{Point, Rect};
 
-use crate::fps::FpsCounter;
+use crate::bindings::KeyBindings;
+use crate::fps::TimingHistory;
+use crate::profiler::Profiler;
+use crate::clock::TickClock;
+use crate::grid::MaterialCounts;
+
+use std::collections::HashMap;
+use std::time::Instant;
 
 static DEBUG_WIDTH: u32 = 300;
-static DEBUG_HEIGHT: u32 = 500;
+static DEBUG_HEIGHT: u32 = 720;
+static FONT_SIZE: u16 = 18;
+static LINE_HEIGHT: i32 = 22;
+// Characters per line that fit in the debug window
+static LINE_WIDTH: usize = 25;
+
+static GRAPH_HEIGHT: i32 = 80;
+// Seconds between recounting the materials, which means going over every cell
+static MATERIAL_COUNT_INTERVAL: f32 = 0.25;
+
+static HEADING_COLOR: Color = Color::RGB(255, 200, 0);
+static TICK_COLOR: Color = Color::RGB(255, 255, 0);
+static RENDER_COLOR: Color = Color::RGB(0, 200, 255);
+
+struct Glyph {
+    texture: Option<Texture>,
+    width: u32,
+    height: u32,
+}
 
 pub struct DebugWindow<'a> {
     canvas: Canvas<Window>,
     font: Font<'a, 'a>,
-    counter: FpsCounter,
+    // Where the next line of text goes
+    cursor_y: i32,
+    glyphs: HashMap<char, Glyph>,
+    // Shown instead of the statistics while toggled on
+    help_lines: Option<Vec<String>>,
+    // Result of the last thing the user did, e.g. saving
+    status: Option<String>,
+    // The last material counts and when they were taken
+    material_counts: Option<(Instant, MaterialCounts)>,
 }
 
 impl <'a> DebugWindow<'a> {
@@ -34,60 +68,289 @@ impl <'a> DebugWindow<'a> {
 
         let canvas = window.into_canvas().build().unwrap();
         // Load a font
-        let mut font: Font<'a, 'a> = ttf_context.load_font("assets/FiraCode-Light.ttf", 24).unwrap();
+        let mut font: Font<'a, 'a> = ttf_context.load_font("assets/FiraCode-Light.ttf", FONT_SIZE).unwrap();
         font.set_style(sdl2::ttf::FontStyle::BOLD);
 
         DebugWindow {
             canvas: canvas,
             font: font,
-            counter: FpsCounter::new(),
+            cursor_y: 0,
+            glyphs: HashMap::new(),
+            help_lines: None,
+            status: None,
+            material_counts: None,
         }
     }
 
-    // NOTE: Inefficient text rendering, but it's "ok enough"
+    // Text is drawn glyph by glyph from a cache of white glyph textures,
+    // tinted with a colour mod, so each character is only rendered once.
     pub fn draw_text(&mut self, text: String, x: i32, y: i32, color: Color) {
-        let surface = self.font
-            .render(&text)
-            .blended(color)
-            .map_err(|e| e.to_string()).unwrap();
-
-        let texture_creator = self.canvas.texture_creator();
-        let texture = texture_creator
-            .create_texture_from_surface(&surface)
-            .map_err(|e| e.to_string())
-            .unwrap();
+        let mut pen_x = x;
+
+        for ch in text.chars() {
+            if !self.glyphs.contains_key(&ch) {
+                let glyph = self.render_glyph(ch);
+                self.glyphs.insert(ch, glyph);
+            }
 
-        let TextureQuery { width, height, .. } = texture.query();
+            let glyph = self.glyphs.get_mut(&ch).unwrap();
 
-        let rect = sdl2::rect::Rect::new(x, y, width, height);
-        let _ = self.canvas.copy(&texture, None, Some(rect));
+            if let Some(texture) = glyph.texture.as_mut() {
+                texture.set_color_mod(color.r, color.g, color.b);
+                texture.set_alpha_mod(color.a);
+
+                let rect = Rect::new(pen_x, y, glyph.width, glyph.height);
+                let _ = self.canvas.copy(texture, None, Some(rect));
+            }
+
+            pen_x += glyph.width as i32;
+        }
+    }
+
+    fn render_glyph(&self, ch: char) -> Glyph {
+        let texture = self.font
+            .render_char(ch)
+            .blended(Color::WHITE)
+            .ok()
+            .and_then(|surface| self.canvas.create_texture_from_surface(&surface).ok());
+
+        let (width, height) = match &texture {
+            Some(texture) => {
+                let TextureQuery { width, height, .. } = texture.query();
+                (width, height)
+            },
+            // Whitespace and glyphs the font doesn't have still take up room
+            None => self.font.size_of_char(ch).unwrap_or((0, 0)),
+        };
+
+        Glyph { texture, width, height }
+    }
+
+    pub fn toggle_help(&mut self, bindings: &KeyBindings) {
+        self.help_lines = match self.help_lines {
+            Some(_) => None,
+            None    => Some(bindings.help_lines()),
+        };
+    }
+
+    pub fn set_status(&mut self, status: String) {
+        self.status = Some(status);
+    }
+
+    fn draw_line(&mut self, text: String, color: Color) {
+        let y = self.cursor_y;
+        self.draw_text(text, 10, y, color);
+        self.cursor_y += LINE_HEIGHT;
+    }
+
+    fn draw_heading(&mut self, text: &str) {
+        self.cursor_y += LINE_HEIGHT / 2;
+        self.draw_line(format!("-- {} --", text), HEADING_COLOR);
     }
 
     // The year was 1995.
-    pub fn render(&mut self, grid: &ParticleGrid, context: &RenderContext, curr_time: f32) {
+    pub fn render(
+        &mut self,
+        grid: &ParticleGrid,
+        context: &RenderContext,
+        shader_error: Option<&str>,
+        profiler: &Profiler,
+        clock: &TickClock,
+        is_paused: bool
+    ) {
         self.canvas.set_draw_color(Color::RGB(0, 0, 0));
         self.canvas.clear();
+        self.cursor_y = 10;
 
-        let grid_y = (context.mouse_y as f32 / context.scale).floor() as i32;
-        let grid_x = (context.mouse_x as f32 / context.scale).floor() as i32;
+        let frame_stats = profiler.frame_counter.frame_time_stats();
+        self.draw_line(
+            format!("FPS: {:.0} (p99 {:.1}ms)", profiler.frame_counter.fps(), frame_stats.p99 * 1000.0),
+            Color::WHITE
+        );
+        self.draw_line(format!("Drawing: {:?}", context.draw_type), Color::WHITE);
 
-        let particle = grid.get(grid_x, grid_y);
+        if let Some(status) = self.status.clone() {
+            let chars: Vec<char> = status.chars().collect();
 
-        let mut sum_water: u64 = 0;
+            for chunk in chars.chunks(LINE_WIDTH) {
+                self.draw_line(chunk.iter().collect(), Color::CYAN);
+            }
+        }
 
-        for p in grid.grid.iter() {
-            if p.p_type == ParticleType::Water {
-                sum_water += p.fill_ratio as u64;
+        if let Some(help_lines) = self.help_lines.clone() {
+            self.draw_heading("Key bindings");
+
+            for line in help_lines {
+                self.draw_line(line, Color::WHITE);
             }
+        } else {
+            self.draw_cursor_panel(grid, context);
+            self.draw_material_panel(grid);
+            self.draw_simulation_panel(profiler, clock, is_paused);
         }
 
-        let fps_text = self.counter.tick(curr_time);
-        self.draw_text(format!("FPS: {}", fps_text), 10, 10, Color::WHITE);
-        self.draw_text(format!("{:?}", particle.p_type), 10, 35, Color::WHITE);
-        self.draw_text(format!("{: >3}/{}", particle.fill_ratio, context.max_fill), 110, 35, Color::WHITE);
-        self.draw_text(format!("{:?}", context.draw_type), 10, 60, Color::WHITE);
-        self.draw_text(format!("{:?}", sum_water), 10, 85, Color::WHITE);
+        if let Some(log) = shader_error {
+            self.draw_shader_error(log);
+        }
 
         self.canvas.present();
     }
+
+    fn draw_cursor_panel(&mut self, grid: &ParticleGrid, context: &RenderContext) {
+        let (grid_x, grid_y) = context.mouse_grid_pos();
+
+        self.draw_heading("Cursor");
+
+        if !grid.in_bounds(grid_x, grid_y) {
+            self.draw_line("Outside the grid".to_string(), Color::GREY);
+            return;
+        }
+
+        let particle = grid.get(grid_x, grid_y).clone();
+
+        self.draw_line(format!("At: {}, {}", grid_x, grid_y), Color::WHITE);
+        self.draw_line(
+            format!("{:?} {}/{}", particle.p_type, particle.fill_ratio, context.max_fill),
+            Color::WHITE
+        );
+    }
+
+    fn draw_material_panel(&mut self, grid: &ParticleGrid) {
+        let (counted_at, counts) = match self.material_counts.take() {
+            Some((counted_at, counts)) if counted_at.elapsed().as_secs_f32() < MATERIAL_COUNT_INTERVAL => {
+                (counted_at, counts)
+            },
+            _ => (Instant::now(), grid.material_counts()),
+        };
+
+        self.draw_heading("Materials");
+        self.draw_line(format!("{:<6}{:>8}{:>8}", "", "cells", "fill"), Color::GREY);
+
+        for p_type in [ParticleType::Water, ParticleType::Sand, ParticleType::Wood].iter() {
+            let id = p_type.id();
+            self.draw_line(
+                format!("{:<6}{:>8}{:>8}", format!("{:?}", p_type), counts.cells[id], counts.fill[id]),
+                Color::WHITE
+            );
+        }
+
+        self.material_counts = Some((counted_at, counts));
+    }
+
+    fn draw_simulation_panel(&mut self, profiler: &Profiler, clock: &TickClock, is_paused: bool) {
+        let tps = profiler.tick_counter.fps();
+        // Unlimited speed has no target, ticks just get as much time as a
+        // normal speed tick would for the graph
+        let interval = clock.tick_interval();
+        let tick_budget = interval.unwrap_or_else(|| clock.tick_time());
+
+        self.draw_heading("Simulation");
+
+        match interval {
+            Some(interval) => self.draw_line(
+                format!("Ticks/s: {:.0}/{:.0} ({})", tps, 1.0 / interval, clock.speed_label()),
+                Color::WHITE
+            ),
+            None => self.draw_line(format!("Ticks/s: {:.0} (max)", tps), Color::WHITE),
+        }
+        self.draw_line(format!("Active cells: {}", profiler.active_cells), Color::WHITE);
+
+        self.draw_line(format!("{:<6}{:>5}{:>5}{:>5}", "ms", "avg", "p95", "max"), Color::GREY);
+        let tick_text = format_timings("tick", &profiler.tick_times);
+        self.draw_line(tick_text, TICK_COLOR);
+        let render_text = format_timings("render", &profiler.render_times);
+        self.draw_line(render_text, RENDER_COLOR);
+
+        // Ticks that take longer than the interval between them can't ever
+        // catch up
+        let falling_behind = match interval {
+            Some(interval) => profiler.tick_times.average() > interval || tps < 0.9 / interval,
+            None           => false,
+        };
+
+        if is_paused {
+            self.draw_line("Paused".to_string(), Color::GREY);
+        } else if falling_behind {
+            self.draw_line("Falling behind!".to_string(), Color::RED);
+        } else {
+            self.draw_line("Keeping up".to_string(), Color::GREEN);
+        }
+
+        self.draw_timing_graph(profiler, tick_budget);
+    }
+
+    // Rolling graph of tick and render times. The red line is the time
+    // between ticks.
+    fn draw_timing_graph(&mut self, profiler: &Profiler, tick_budget: f32) {
+        let left = 10;
+        let top = self.cursor_y + 5;
+        let width = DEBUG_WIDTH as i32 - 20;
+        let bottom = top + GRAPH_HEIGHT;
+
+        // The tick budget sits at three quarters of the graph's height
+        let y_for = |duration: f32| {
+            let height = (duration / tick_budget * 0.75 * GRAPH_HEIGHT as f32) as i32;
+            bottom - height.min(GRAPH_HEIGHT)
+        };
+
+        self.canvas.set_draw_color(Color::RGB(40, 40, 40));
+        let _ = self.canvas.fill_rect(Rect::new(left, top, width as u32, GRAPH_HEIGHT as u32));
+
+        self.canvas.set_draw_color(Color::RED);
+        let _ = self.canvas.draw_line((left, y_for(tick_budget)), (left + width, y_for(tick_budget)));
+
+        for (history, color) in [
+            (&profiler.render_times, RENDER_COLOR),
+            (&profiler.tick_times, TICK_COLOR),
+        ].iter() {
+            let points: Vec<Point> = history.samples()
+                .enumerate()
+                .map(|(i, d)| {
+                    Point::new(left + (i as i32 * width) / history.capacity() as i32, y_for(*d))
+                })
+                .collect();
+
+            self.canvas.set_draw_color(*color);
+            let _ = self.canvas.draw_lines(points.as_slice());
+        }
+
+        self.cursor_y = bottom + LINE_HEIGHT / 2;
+    }
+
+    fn draw_shader_error(&mut self, log: &str) {
+        self.draw_heading("Shader error");
+
+        for line in log.lines() {
+            let chars: Vec<char> = line.chars().collect();
+
+            for chunk in chars.chunks(LINE_WIDTH) {
+                if self.cursor_y > DEBUG_HEIGHT as i32 {
+                    return;
+                }
+
+                self.draw_line(chunk.iter().collect(), Color::RED);
+            }
+        }
+    }
+}
+
+fn format_timings(label: &str, history: &TimingHistory) -> String {
+    format!(
+        "{:<6}{:>5.1}{:>5.1}{:>5.1}",
+        label,
+        history.average() * 1000.0,
+        history.percentile(0.95) * 1000.0,
+        history.max() * 1000.0
+    )
+}
+
+impl <'a> Drop for DebugWindow<'a> {
+    fn drop(&mut self) {
+        for (_, glyph) in self.glyphs.drain() {
+            if let Some(texture) = glyph.texture {
+                // Safe since the canvas that created it is still alive
+                unsafe { texture.destroy() };
+            }
+        }
+    }
 }
@@ -0,0 +1,156 @@
+use crate::grid::ParticleGrid;
+use crate::image::{render_rgb, write_png};
+use crate::palette::Palette;
+use crate::save_state;
+
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Where exports started from the app go
+static EXPORT_DIR: &str = "exports";
+// Trades GIF colour quality for encoding time, from 1 (best) to 30
+static GIF_QUANTIZE_SPEED: i32 = 10;
+
+enum Output {
+    Gif(gif::Encoder<BufWriter<File>>),
+    // A directory of numbered PNGs
+    Images(String),
+}
+
+// Writes every Nth tick of a simulation to an animated GIF, or to a directory
+// of PNGs for anything else
+pub struct Exporter {
+    path: String,
+    output: Output,
+    every: u64,
+    scale: u32,
+    // In hundredths of a second, for GIFs
+    frame_delay: u16,
+    grid_width: i32,
+    grid_height: i32,
+    frames: usize,
+    last_frame_tick: Option<u64>,
+}
+
+impl Exporter {
+    // `tick_time` is the simulated length of a tick, for the GIF frame delay
+    pub fn create(
+        path: &str,
+        grid: &ParticleGrid,
+        every: u64,
+        scale: u32,
+        tick_time: f32
+    ) -> Result<Exporter, String> {
+        let every = every.max(1);
+        let scale = scale.max(1);
+
+        let output = if path.ends_with(".gif") {
+            let too_big = || format!("{} would be too big for a GIF, try a smaller scale", path);
+            let width: u16 = (grid.width as u32 * scale).try_into().map_err(|_| too_big())?;
+            let height: u16 = (grid.height as u32 * scale).try_into().map_err(|_| too_big())?;
+
+            create_parent_dir(path)?;
+
+            let file = File::create(path).map_err(|e| format!("Could not create {}: {}", path, e))?;
+            let mut encoder = gif::Encoder::new(BufWriter::new(file), width, height, &[])
+                .map_err(|e| format!("Could not write {}: {}", path, e))?;
+            encoder.set_repeat(gif::Repeat::Infinite)
+                .map_err(|e| format!("Could not write {}: {}", path, e))?;
+
+            Output::Gif(encoder)
+        } else {
+            fs::create_dir_all(path).map_err(|e| format!("Could not create {}: {}", path, e))?;
+            Output::Images(path.to_string())
+        };
+
+        Ok(Exporter {
+            path: path.to_string(),
+            output,
+            every,
+            scale,
+            frame_delay: (every as f32 * tick_time * 100.0).round().max(1.0) as u16,
+            grid_width: grid.width,
+            grid_height: grid.height,
+            frames: 0,
+            last_frame_tick: None,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    // Write a frame if it's been `every` ticks since the last one
+    pub fn capture(
+        &mut self,
+        grid: &ParticleGrid,
+        palette: &Palette,
+        max_fill: u8,
+        tick: u64
+    ) -> Result<(), String> {
+        // Always take the frame if the timeline was rewound past the last one
+        if let Some(last) = self.last_frame_tick {
+            if tick >= last && tick < last + self.every {
+                return Ok(());
+            }
+        }
+
+        if grid.width != self.grid_width || grid.height != self.grid_height {
+            return Err(format!("The world was resized, stopped exporting to {}", self.path));
+        }
+
+        let width = self.grid_width as u32 * self.scale;
+        let height = self.grid_height as u32 * self.scale;
+        let pixels = render_rgb(grid, palette, max_fill, self.scale);
+
+        match &mut self.output {
+            Output::Gif(encoder) => {
+                let mut frame = gif::Frame::from_rgb_speed(
+                    width as u16,
+                    height as u16,
+                    &pixels,
+                    GIF_QUANTIZE_SPEED
+                );
+                frame.delay = self.frame_delay;
+
+                encoder.write_frame(&frame)
+                    .map_err(|e| format!("Could not write {}: {}", self.path, e))?;
+            },
+            Output::Images(dir) => {
+                let path = format!("{}/frame-{:06}.png", dir, self.frames);
+                write_png(&path, &pixels, width, height)?;
+            },
+        }
+
+        self.frames += 1;
+        self.last_frame_tick = Some(tick);
+        Ok(())
+    }
+}
+
+fn create_parent_dir(path: &str) -> Result<(), String> {
+    match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => {
+            fs::create_dir_all(dir).map_err(|e| format!("Could not create {:?}: {}", dir, e))
+        },
+        _ => Ok(()),
+    }
+}
+
+// A new GIF path in the export directory, e.g. exports/export-1623456789.gif,
+// with a counter if there's already an export from that second
+pub fn timestamped_path() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    save_state::unused_path(&format!("{}/export-{}", EXPORT_DIR, secs), "gif")
+}
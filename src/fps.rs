@@ -1,46 +1,228 @@
-const FPS_DISPLAY_TIME_DELTA: f32 = 0.1;
-const FPS_HISTORY_SIZE: usize = 10;
+use std::collections::VecDeque;
 
+// Summary of a window of durations, in seconds
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TimingStats {
+    pub min: f32,
+    pub avg: f32,
+    pub p50: f32,
+    pub p99: f32,
+    pub max: f32,
+}
+
+// Ring buffer of the most recent durations, in seconds
+pub struct TimingHistory {
+    capacity: usize,
+    samples: VecDeque<f32>,
+}
+
+impl TimingHistory {
+    pub fn new(capacity: usize) -> TimingHistory {
+        assert!(capacity > 0, "TimingHistory needs room for at least one sample");
+
+        TimingHistory {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, duration: f32) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(duration);
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // Oldest first
+    pub fn samples(&self) -> impl Iterator<Item = &f32> {
+        self.samples.iter()
+    }
+
+    pub fn average(&self) -> f32 {
+        if self.samples.is_empty() {
+            0.0
+        } else {
+            self.samples.iter().sum::<f32>() / self.samples.len() as f32
+        }
+    }
+
+    pub fn max(&self) -> f32 {
+        self.samples.iter().cloned().fold(0.0, f32::max)
+    }
+
+    // Nearest rank percentile, p in [0, 1]
+    pub fn percentile(&self, p: f32) -> f32 {
+        percentile_of_sorted(&self.sorted(), p)
+    }
+
+    pub fn stats(&self) -> TimingStats {
+        let sorted = self.sorted();
+
+        if sorted.is_empty() {
+            return Default::default();
+        }
+
+        TimingStats {
+            min: sorted[0],
+            avg: self.average(),
+            p50: percentile_of_sorted(&sorted, 0.5),
+            p99: percentile_of_sorted(&sorted, 0.99),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+
+    fn sorted(&self) -> Vec<f32> {
+        let mut sorted: Vec<f32> = self.samples.iter().cloned().collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        sorted
+    }
+}
+
+fn percentile_of_sorted(sorted: &[f32], p: f32) -> f32 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+
+    let index = ((sorted.len() - 1) as f32 * p).round() as usize;
+    sorted[index]
+}
+
+// Measures how often something happens, e.g. render frames or physics ticks,
+// from the timestamps it happened at.
 pub struct FpsCounter {
-    last_display_time: f32,
-    last_tick_time: f32,
-    fps_history: [f32; FPS_HISTORY_SIZE],
-    fps_history_next: usize,
-    display_text: String,
+    last_tick_time: Option<f32>,
+    frame_times: TimingHistory,
 }
 
 impl FpsCounter {
-    pub fn new() -> FpsCounter {
+    pub fn new(history_size: usize) -> FpsCounter {
         FpsCounter {
-            last_display_time: 0.0,
-            last_tick_time: 0.0,
-            fps_history: [0.0; FPS_HISTORY_SIZE],
-            fps_history_next: 0,
-            display_text: String::new(),
+            last_tick_time: None,
+            frame_times: TimingHistory::new(history_size),
         }
     }
 
-    pub fn tick(&mut self, t: f32) -> String {
-        let tick_fps = 1.0 / (t - self.last_tick_time as f32);
-        self.last_tick_time = t;
+    // Record an event at time t, in seconds
+    pub fn tick(&mut self, t: f32) {
+        if let Some(last) = self.last_tick_time {
+            self.frame_times.push((t - last).max(0.0));
+        }
 
-        self.fps_history[self.fps_history_next] = tick_fps;
-        self.fps_history_next = (self.fps_history_next + 1) % self.fps_history.len();
+        self.last_tick_time = Some(t);
+    }
 
-        if t > self.last_display_time + FPS_DISPLAY_TIME_DELTA {
-            self.last_display_time = t;
-            let display_fps = average(&self.fps_history);
-            self.display_text = format!("{:.0}", display_fps);
-        }
+    // Times between events, in seconds
+    pub fn frame_time_stats(&self) -> TimingStats {
+        self.frame_times.stats()
+    }
+
+    // Events per second, averaged over the history
+    pub fn fps(&self) -> f32 {
+        let avg = self.frame_times.average();
 
-        self.display_text.clone()
+        if avg > 0.0 {
+            1.0 / avg
+        } else {
+            0.0
+        }
     }
 }
 
-fn average(arr: &[f32]) -> f32 {
-    let mut s = 0.0;
-    for i in arr {
-        s += i / arr.len() as f32;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter_with_frame_times(history_size: usize, frame_times: &[f32]) -> FpsCounter {
+        let mut counter = FpsCounter::new(history_size);
+        let mut t = 0.0;
+
+        counter.tick(t);
+
+        for frame_time in frame_times {
+            t += frame_time;
+            counter.tick(t);
+        }
+
+        counter
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn empty_counter_reports_zeroes() {
+        let mut counter = FpsCounter::new(10);
+        counter.tick(1.0);
+
+        assert_eq!(counter.fps(), 0.0);
+        assert_eq!(counter.frame_time_stats(), Default::default());
+    }
+
+    #[test]
+    fn steady_frames() {
+        let counter = counter_with_frame_times(10, &[0.02; 30]);
+        let stats = counter.frame_time_stats();
+
+        assert_close(counter.fps(), 50.0);
+        assert_close(stats.min, 0.02);
+        assert_close(stats.avg, 0.02);
+        assert_close(stats.p50, 0.02);
+        assert_close(stats.p99, 0.02);
+        assert_close(stats.max, 0.02);
+    }
+
+    #[test]
+    fn stutter_shows_in_the_tail() {
+        let mut frame_times = vec![0.01; 98];
+        frame_times.extend_from_slice(&[0.5, 0.5]);
+
+        let stats = counter_with_frame_times(100, &frame_times).frame_time_stats();
+
+        assert_close(stats.min, 0.01);
+        assert_close(stats.p50, 0.01);
+        assert_close(stats.p99, 0.5);
+        assert_close(stats.max, 0.5);
+        assert_close(stats.avg, (0.01 * 98.0 + 1.0) / 100.0);
+    }
+
+    #[test]
+    fn old_frames_fall_out_of_the_history() {
+        let mut frame_times = vec![1.0; 5];
+        frame_times.extend_from_slice(&[0.1; 4]);
+
+        let counter = counter_with_frame_times(4, &frame_times);
+
+        assert_eq!(counter.frame_times.samples().count(), 4);
+        assert_close(counter.frame_time_stats().max, 0.1);
+        assert_close(counter.fps(), 10.0);
+    }
+
+    #[test]
+    fn several_ticks_at_once_still_average_out() {
+        // Physics ticks catching up run back to back within one frame
+        let counter = counter_with_frame_times(10, &[0.1, 0.0, 0.1, 0.0]);
+
+        assert_close(counter.frame_time_stats().min, 0.0);
+        assert_close(counter.fps(), 20.0);
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let mut history = TimingHistory::new(5);
+
+        for d in [5.0, 1.0, 4.0, 2.0, 3.0].iter() {
+            history.push(*d);
+        }
+
+        assert_close(history.percentile(0.0), 1.0);
+        assert_close(history.percentile(0.5), 3.0);
+        assert_close(history.percentile(0.95), 5.0);
+        assert_close(history.percentile(1.0), 5.0);
     }
-    s
 }
@@ -0,0 +1,274 @@
+// Procedurally generated starting worlds: sand terrain from layered value
+// noise, carved out caves, wooden platforms and lakes
+
+use crate::grid::{Particle, ParticleGrid, ParticleType};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Preset {
+    Hills,
+    Lakes,
+    Caves,
+    Platforms,
+    Mixed,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [
+        Preset::Hills,
+        Preset::Lakes,
+        Preset::Caves,
+        Preset::Platforms,
+        Preset::Mixed,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Hills     => "hills",
+            Preset::Lakes     => "lakes",
+            Preset::Caves     => "caves",
+            Preset::Platforms => "platforms",
+            Preset::Mixed     => "mixed",
+        }
+    }
+}
+
+impl std::str::FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Preset, String> {
+        Preset::ALL.iter().cloned().find(|p| p.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = Preset::ALL.iter().map(|p| p.name()).collect();
+            format!("Unknown preset '{}', expected one of {}", s, names.join(", "))
+        })
+    }
+}
+
+// What to put in a world. Heights and levels are fractions of the world's
+// height, measured from the bottom.
+#[derive(Clone, Debug)]
+pub struct Generator {
+    pub seed: u64,
+    // Average height of the sand, and how far the hills stray from it
+    pub terrain_height: f32,
+    pub terrain_roughness: f32,
+    // Roughly this fraction of the sand is hollowed out into caves
+    pub cave_density: f32,
+    pub platforms: usize,
+    // Empty space below this height fills with water
+    pub water_level: Option<f32>,
+}
+
+impl Generator {
+    pub fn preset(preset: Preset, seed: u64) -> Generator {
+        let plain = Generator {
+            seed,
+            terrain_height: 0.35,
+            terrain_roughness: 0.2,
+            cave_density: 0.0,
+            platforms: 0,
+            water_level: None,
+        };
+
+        match preset {
+            Preset::Hills => plain,
+            Preset::Lakes => Generator {
+                terrain_roughness: 0.3,
+                water_level: Some(0.4),
+                ..plain
+            },
+            Preset::Caves => Generator {
+                terrain_height: 0.6,
+                cave_density: 0.35,
+                ..plain
+            },
+            Preset::Platforms => Generator {
+                terrain_height: 0.15,
+                terrain_roughness: 0.1,
+                platforms: 8,
+                ..plain
+            },
+            Preset::Mixed => Generator {
+                terrain_height: 0.45,
+                terrain_roughness: 0.3,
+                cave_density: 0.25,
+                platforms: 4,
+                water_level: Some(0.35),
+                ..plain
+            },
+        }
+    }
+
+    // The same world every time for the same settings and size
+    pub fn generate(&self, width: i32, height: i32, max_fill: u8) -> ParticleGrid {
+        let mut grid = ParticleGrid::new(width, height);
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+
+        let mut particle = |p_type| Particle {
+            p_type,
+            fill_ratio: max_fill,
+            shade: rng.gen(),
+        };
+
+        // Features scale with the world, so a bigger world has wider hills
+        // rather than more of them
+        let terrain_noise = Noise::new(self.seed, width as f32 / 4.0);
+        let cave_noise = Noise::new(self.seed.wrapping_add(1), width.min(height) as f32 / 6.0);
+
+        let water_y = self.water_level.map(|level| height - (level * height as f32) as i32);
+
+        for x in 0..width {
+            let hill = terrain_noise.sample(x as f32, 0.0) * 2.0 - 1.0;
+            let surface = self.terrain_height + hill * self.terrain_roughness;
+            let surface_y = height - (surface.max(0.0) * height as f32) as i32;
+
+            for y in 0..height {
+                let is_cave = self.cave_density > 0.0 &&
+                    // Keep a crust on top so caves don't open up everywhere
+                    y > surface_y + 2 &&
+                    cave_noise.sample(x as f32, y as f32) < self.cave_density;
+
+                if y >= surface_y && !is_cave {
+                    grid.set(x, y, particle(ParticleType::Sand));
+                } else if matches!(water_y, Some(water_y) if y >= water_y) {
+                    grid.set(x, y, particle(ParticleType::Water));
+                }
+            }
+        }
+
+        self.add_platforms(&mut grid, &mut ChaCha8Rng::seed_from_u64(self.seed.wrapping_add(2)), max_fill);
+
+        grid
+    }
+
+    // Wooden beams in the open air above the terrain
+    fn add_platforms(&self, grid: &mut ParticleGrid, rng: &mut ChaCha8Rng, max_fill: u8) {
+        if grid.width < 4 || grid.height < 4 {
+            return;
+        }
+
+        for _ in 0..self.platforms {
+            let length = rng.gen_range(grid.width / 10..=grid.width / 4).max(2);
+            let left = rng.gen_range(0..grid.width - length);
+            let y = rng.gen_range(grid.height / 8..grid.height / 2);
+
+            for x in left..left + length {
+                if grid.get(x, y).p_type == ParticleType::Empty {
+                    grid.set(x, y, Particle {
+                        p_type: ParticleType::Wood,
+                        fill_ratio: max_fill,
+                        shade: rng.gen(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+// Smooth 2D noise in [0, 1), from a few octaves of interpolated random values
+// on a lattice
+struct Noise {
+    seed: u64,
+    // Distance between lattice points of the coarsest octave, in cells
+    scale: f32,
+}
+
+impl Noise {
+    fn new(seed: u64, scale: f32) -> Noise {
+        Noise { seed, scale: scale.max(1.0) }
+    }
+
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let mut total = 0.0;
+        let mut amplitude = 0.5;
+        let mut weight = 0.0;
+        let mut frequency = 1.0 / self.scale;
+
+        for octave in 0..4 {
+            total += amplitude * self.lattice(x * frequency, y * frequency, octave);
+            weight += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+
+        total / weight
+    }
+
+    fn lattice(&self, x: f32, y: f32, octave: u64) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (smoothstep(x - x0), smoothstep(y - y0));
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let corner = |dx, dy| hash(self.seed ^ octave.wrapping_mul(0x9e37_79b9), x0 + dx, y0 + dy);
+
+        let top = lerp(corner(0, 0), corner(1, 0), tx);
+        let bottom = lerp(corner(0, 1), corner(1, 1), tx);
+
+        lerp(top, bottom, ty)
+    }
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// A random value in [0, 1) for each lattice point, from splitmix64
+fn hash(seed: u64, x: i64, y: i64) -> f32 {
+    let mut z = seed
+        .wrapping_add((x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        .wrapping_add((y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f));
+
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+
+    (z >> 40) as f32 / (1u64 << 24) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(grid: &ParticleGrid, p_type: ParticleType) -> usize {
+        grid.grid.iter().filter(|p| p.p_type == p_type).count()
+    }
+
+    #[test]
+    fn same_seed_same_world() {
+        let generator = Generator::preset(Preset::Mixed, 7);
+
+        assert_eq!(generator.generate(120, 80, 8), generator.generate(120, 80, 8));
+        assert_ne!(generator.generate(120, 80, 8), Generator::preset(Preset::Mixed, 8).generate(120, 80, 8));
+    }
+
+    #[test]
+    fn presets_have_their_features() {
+        let generate = |preset| Generator::preset(preset, 3).generate(200, 100, 8);
+
+        let hills = generate(Preset::Hills);
+        assert!(count(&hills, ParticleType::Sand) > 0);
+        assert_eq!(count(&hills, ParticleType::Water), 0);
+
+        assert!(count(&generate(Preset::Lakes), ParticleType::Water) > 0);
+        assert!(count(&generate(Preset::Platforms), ParticleType::Wood) > 0);
+
+        // Caves hollow out sand that would otherwise be there
+        let caves = generate(Preset::Caves);
+        let solid = Generator { cave_density: 0.0, ..Generator::preset(Preset::Caves, 3) }.generate(200, 100, 8);
+        assert!(count(&caves, ParticleType::Sand) < count(&solid, ParticleType::Sand));
+    }
+
+    #[test]
+    fn tiny_worlds_work() {
+        for preset in Preset::ALL.iter() {
+            Generator::preset(*preset, 0).generate(1, 1, 8);
+            Generator::preset(*preset, 0).generate(3, 50, 8);
+        }
+    }
+}
@@ -1,10 +1,22 @@
 use serde::{Serialize, Deserialize};
 use std::convert::{TryInto};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Particle {
     pub p_type: ParticleType,
     pub fill_ratio: u8,
+    // Random per-particle colour variation, picked once when it's created
+    pub shade: u8,
+}
+
+impl Particle {
+    pub fn new(p_type: ParticleType, fill_ratio: u8) -> Particle {
+        Particle {
+            p_type,
+            fill_ratio,
+            shade: rand::random(),
+        }
+    }
 }
 
 impl Default for Particle {
@@ -12,11 +24,12 @@ impl Default for Particle {
         Particle {
             p_type: ParticleType::Empty,
             fill_ratio: 0,
+            shade: 0,
         }
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Grid<T> where T: Clone {
     pub width: i32,
     pub height: i32,
@@ -69,12 +82,142 @@ impl<T> Grid<T> where T: Clone + Default {
         self.set(x, y, Default::default())
     }
 
-    #[allow(dead_code)]
     pub fn clear_all(&mut self) {
         for i in 0..self.grid.len() {
             self.grid[i] = Default::default()
         }
     }
+
+    // Copy into a grid of a new size. The anchor decides which part of the old
+    // grid is kept when cropping, and where the new space goes when growing.
+    pub fn resized(&self, width: i32, height: i32, anchor: Anchor) -> Grid<T> {
+        let mut resized = Grid::new(width, height);
+
+        let (offset_x, offset_y) = anchor.offset(width - self.width, height - self.height);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let new_x = x + offset_x;
+                let new_y = y + offset_y;
+
+                if resized.in_bounds(new_x, new_y) {
+                    resized.set(new_x, new_y, self.get(x, y).clone());
+                }
+            }
+        }
+
+        resized
+    }
+
+    // The width by height block with its top left at (left, top). Parts of it
+    // outside this grid are left as default.
+    pub fn cropped(&self, left: i32, top: i32, width: i32, height: i32) -> Grid<T> {
+        let mut cropped = Grid::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                if self.in_bounds(left + x, top + y) {
+                    cropped.set(x, y, self.get(left + x, top + y).clone());
+                }
+            }
+        }
+
+        cropped
+    }
+
+    // A quarter turn clockwise
+    pub fn rotated(&self) -> Grid<T> {
+        let mut rotated = Grid::new(self.height, self.width);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                rotated.set(self.height - 1 - y, x, self.get(x, y).clone());
+            }
+        }
+
+        rotated
+    }
+
+    // Mirrored left to right
+    pub fn flipped_horizontally(&self) -> Grid<T> {
+        let mut flipped = Grid::new(self.width, self.height);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                flipped.set(self.width - 1 - x, y, self.get(x, y).clone());
+            }
+        }
+
+        flipped
+    }
+
+    // Mirrored top to bottom
+    pub fn flipped_vertically(&self) -> Grid<T> {
+        let mut flipped = Grid::new(self.width, self.height);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                flipped.set(x, self.height - 1 - y, self.get(x, y).clone());
+            }
+        }
+
+        flipped
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum AnchorPos {
+    Start,
+    Center,
+    End,
+}
+
+impl AnchorPos {
+    fn offset(&self, delta: i32) -> i32 {
+        match self {
+            AnchorPos::Start  => 0,
+            AnchorPos::Center => delta / 2,
+            AnchorPos::End    => delta,
+        }
+    }
+}
+
+// Which edge of a grid stays put when it is resized
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Anchor {
+    pub horizontal: AnchorPos,
+    pub vertical: AnchorPos,
+}
+
+impl Anchor {
+    fn offset(&self, delta_width: i32, delta_height: i32) -> (i32, i32) {
+        (self.horizontal.offset(delta_width), self.vertical.offset(delta_height))
+    }
+}
+
+impl std::str::FromStr for Anchor {
+    type Err = String;
+
+    // Accepts things like "bottom-center", "top-left", "right" or "centre"
+    fn from_str(s: &str) -> Result<Anchor, String> {
+        let mut anchor = Anchor {
+            horizontal: AnchorPos::Center,
+            vertical: AnchorPos::Center,
+        };
+
+        for part in s.to_lowercase().split('-') {
+            match part {
+                "top"               => anchor.vertical = AnchorPos::Start,
+                "bottom"            => anchor.vertical = AnchorPos::End,
+                "left"              => anchor.horizontal = AnchorPos::Start,
+                "right"             => anchor.horizontal = AnchorPos::End,
+                "center" | "centre" => {},
+                _ => return Err(format!("Unknown anchor '{}'", s)),
+            }
+        }
+
+        Ok(anchor)
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -85,9 +228,46 @@ pub enum ParticleType {
     Empty
 }
 
+impl ParticleType {
+    // In id order
+    pub const ALL: [ParticleType; 4] = [
+        ParticleType::Empty,
+        ParticleType::Water,
+        ParticleType::Sand,
+        ParticleType::Wood,
+    ];
+
+    // Identifier shared with the shaders and palettes
+    pub fn id(&self) -> usize {
+        match self {
+            ParticleType::Empty => 0,
+            ParticleType::Water => 1,
+            ParticleType::Sand  => 2,
+            ParticleType::Wood  => 3,
+        }
+    }
+}
+
 pub type ParticleGrid = Grid<Particle>;
 
+// How much of each particle type a grid holds, indexed by `ParticleType::id`
+pub struct MaterialCounts {
+    pub cells: [u64; 4],
+    pub fill: [u64; 4],
+}
+
 impl Grid<Particle> {
+    pub fn material_counts(&self) -> MaterialCounts {
+        let mut counts = MaterialCounts { cells: [0; 4], fill: [0; 4] };
+
+        for p in self.grid.iter() {
+            counts.cells[p.p_type.id()] += 1;
+            counts.fill[p.p_type.id()] += p.fill_ratio as u64;
+        }
+
+        counts
+    }
+
     pub fn is_empty(&self, x: i32, y: i32) -> bool {
         if x < 0 || x >= self.width || y < 0 || y >= self.height {
             false
@@ -95,4 +275,98 @@ impl Grid<Particle> {
             self.get(x, y).p_type == ParticleType::Empty
         }
     }
+
+    // The cells joined to (x, y) by edges that hold the same type of particle,
+    // as the bucket tool fills them. None if there are more than `limit`.
+    pub fn connected_region(&self, x: i32, y: i32, limit: usize) -> Option<Vec<(i32, i32)>> {
+        if !self.in_bounds(x, y) {
+            return Some(Vec::new());
+        }
+
+        let p_type = self.get(x, y).p_type;
+        let mut seen = vec![false; self.grid.len()];
+        let mut region = Vec::new();
+        let mut stack = vec![(x, y)];
+        seen[grid_index!(self, x, y)] = true;
+
+        while let Some((x, y)) = stack.pop() {
+            region.push((x, y));
+
+            if region.len() > limit {
+                return None;
+            }
+
+            for &(nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)].iter() {
+                if self.in_bounds(nx, ny) && !seen[grid_index!(self, nx, ny)] && self.get(nx, ny).p_type == p_type {
+                    seen[grid_index!(self, nx, ny)] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+
+        Some(region)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A wooden cup with a short left wall, and a loose bit of wood above it
+    fn cup() -> ParticleGrid {
+        let mut grid = ParticleGrid::new(6, 5);
+
+        for &(x, y) in [(3, 0), (0, 2), (0, 3), (5, 1), (5, 2), (5, 3)].iter() {
+            grid.set(x, y, Particle::new(ParticleType::Wood, 8));
+        }
+        for x in 0..6 {
+            grid.set(x, 4, Particle::new(ParticleType::Wood, 8));
+        }
+
+        grid
+    }
+
+    #[test]
+    fn region_stays_inside_walls() {
+        let grid = cup();
+
+        let wood = grid.connected_region(0, 4, 100).unwrap();
+        assert_eq!(wood.len(), 11);
+        assert!(!wood.contains(&(3, 0)));
+
+        // The inside of the cup joins the space above the short wall
+        let empty = grid.connected_region(2, 3, 100).unwrap();
+        assert_eq!(empty.len(), 6 * 5 - 12);
+        assert!(empty.contains(&(0, 0)));
+    }
+
+    // Cell values are their position in the original, row by row
+    fn numbered(width: i32, height: i32) -> Grid<i32> {
+        Grid { width, height, grid: (0..width * height).collect() }
+    }
+
+    #[test]
+    fn crop_rotate_and_flip() {
+        let grid = numbered(3, 2);
+
+        assert_eq!(grid.cropped(1, 0, 2, 2).grid, vec![1, 2, 4, 5]);
+        assert_eq!(grid.cropped(2, 1, 2, 2).grid, vec![5, 0, 0, 0]);
+
+        let rotated = grid.rotated();
+        assert_eq!((rotated.width, rotated.height), (2, 3));
+        assert_eq!(rotated.grid, vec![3, 0, 4, 1, 5, 2]);
+        assert_eq!(rotated.rotated().rotated().rotated().grid, grid.grid);
+
+        assert_eq!(grid.flipped_horizontally().grid, vec![2, 1, 0, 5, 4, 3]);
+        assert_eq!(grid.flipped_vertically().grid, vec![3, 4, 5, 0, 1, 2]);
+    }
+
+    #[test]
+    fn region_over_limit() {
+        let grid = cup();
+
+        assert!(grid.connected_region(2, 3, 17).is_none());
+        assert!(grid.connected_region(2, 3, 18).is_some());
+        assert_eq!(grid.connected_region(-1, 0, 10), Some(Vec::new()));
+    }
 }
@@ -0,0 +1,98 @@
+use crate::grid::{Particle, ParticleGrid, ParticleType};
+use crate::palette::Palette;
+
+use std::fs::File;
+use std::io::BufWriter;
+
+// Render a grid on the CPU, as rows of RGB pixels, `scale` pixels per cell
+pub fn render_rgb(grid: &ParticleGrid, palette: &Palette, max_fill: u8, scale: u32) -> Vec<u8> {
+    let scale = scale as usize;
+    let width = grid.width as usize * scale;
+    let mut pixels = Vec::with_capacity(width * grid.height as usize * scale * 3);
+
+    for y in 0..grid.height {
+        let mut row = Vec::with_capacity(width * 3);
+
+        for x in 0..grid.width {
+            let rgb = palette.particle_color(grid.get(x, y), max_fill);
+
+            for _ in 0..scale {
+                row.extend_from_slice(&rgb);
+            }
+        }
+
+        for _ in 0..scale {
+            pixels.extend_from_slice(&row);
+        }
+    }
+
+    pixels
+}
+
+pub fn write_png(path: &str, pixels: &[u8], width: u32, height: u32) -> Result<(), String> {
+    let png_err = |e: png::EncodingError| format!("Could not write {}: {}", path, e);
+
+    let file = File::create(path).map_err(|e| format!("Could not create {}: {}", path, e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .map_err(png_err)
+}
+
+// Returns RGB pixels, the width and the height
+pub fn read_png(path: &str) -> Result<(Vec<u8>, u32, u32), String> {
+    let png_err = |e: png::DecodingError| format!("Could not read {}: {}", path, e);
+
+    let file = File::open(path).map_err(|e| format!("Could not open {}: {}", path, e))?;
+    let mut decoder = png::Decoder::new(file);
+    // Turns indexed colour into RGB, and 16 bit channels into 8 bit
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+    let (info, mut reader) = decoder.read_info().map_err(png_err)?;
+    let mut buffer = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer).map_err(png_err)?;
+
+    let pixels = match info.color_type {
+        png::ColorType::RGB  => buffer,
+        png::ColorType::RGBA => buffer.chunks(4).flat_map(|p| p[..3].to_vec()).collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|v| vec![*v; 3]).collect(),
+        png::ColorType::GrayscaleAlpha => buffer.chunks(2).flat_map(|p| vec![p[0]; 3]).collect(),
+        other => return Err(format!("{} has unsupported colour type {:?}", path, other)),
+    };
+
+    Ok((pixels, info.width, info.height))
+}
+
+// Turn an image back into a world, each `scale` by `scale` block of pixels
+// becoming the particle type with the closest palette colour
+pub fn grid_from_rgb(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    scale: u32,
+    palette: &Palette,
+    max_fill: u8
+) -> ParticleGrid {
+    let scale = scale.max(1);
+    let mut grid = ParticleGrid::new((width / scale) as i32, (height / scale) as i32);
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            // Sample the middle of each block
+            let px = x as u32 * scale + scale / 2;
+            let py = y as u32 * scale + scale / 2;
+            let i = ((py * width + px) * 3) as usize;
+
+            let p_type = palette.closest_type([pixels[i], pixels[i + 1], pixels[i + 2]]);
+
+            if p_type != ParticleType::Empty {
+                grid.set(x, y, Particle::new(p_type, max_fill));
+            }
+        }
+    }
+
+    grid
+}
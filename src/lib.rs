@@ -0,0 +1,20 @@
+// The simulation and its file formats, kept apart from the SDL app so they
+// can be used and tested without a window
+
+pub mod autosave;
+pub mod bench;
+pub mod clock;
+pub mod config;
+pub mod export;
+pub mod fps;
+pub mod generator;
+pub mod grid;
+pub mod image;
+pub mod palette;
+pub mod physics;
+pub mod recording;
+pub mod save_state;
+pub mod scenes;
+pub mod shapes;
+pub mod stamps;
+pub mod timeline;
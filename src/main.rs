@@ -1,13 +1,18 @@
 extern crate clap;
 extern crate sdl2;
 
-mod fps;
-mod physics;
-mod grid;
+use magicpixel::{autosave, bench, clock, config, export, fps, generator, grid, image, palette, physics, recording, save_state, scenes, shapes, stamps, timeline};
+
+mod bindings;
+mod camera;
+mod cli;
+mod commands;
 mod render;
+mod tools;
+mod toolbar;
 mod debug;
 mod render_context;
-mod save_state;
+mod profiler;
 
 use std::cmp::max;
 use std::cmp::min;
@@ -15,45 +20,103 @@ use physics::Physics;
 use grid::*;
 use render::*;
 use debug::DebugWindow;
+use export::Exporter;
 use render_context::RenderContext;
 use save_state::SaveState;
+use palette::Palette;
+use bindings::{Action, KeyBindings};
+use autosave::Autosaver;
+use clock::TickClock;
+use cli::{Command, Opts, RunOpts};
+use profiler::Profiler;
+use recording::{Input, Recorder};
+use timeline::Timeline;
+use shapes::Shape;
+use tools::Tool;
+use toolbar::{Toolbar, ToolbarItem, ToolbarState};
 
-use clap::{AppSettings, Clap};
+use clap::Clap;
 
 use sdl2::event::Event;
 use sdl2::event::WindowEvent;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::Mod;
 use sdl2::mouse::{ MouseButton };
 
 use std::time::{SystemTime};
 
-static TICK_TIME: f32 = 0.05;
-
-#[derive(Clap)]
-#[clap(setting = AppSettings::ColoredHelp)]
-pub struct Opts {
-    #[clap(short = 'f', long, default_value = "8")]
-    max_fill: u8,
-    #[clap(short = 's', long)]
-    file_path: Option<String>,
-    #[clap(short = 'h', long)]
-    height: Option<i32>,
-    #[clap(short = 'w', long)]
-    width: Option<i32>,
-}
+// Seconds per tick at normal speed, unless given with --tick-time
+pub static TICK_TIME: f32 = 0.05;
+// Most ticks a frame runs to catch up. Beyond that the simulation slows down
+// instead, so slow ticks can't leave no time for drawing and input.
+static MAX_CATCH_UP_TICKS: u32 = 8;
+// At unlimited speed, ticks run until a frame has taken this many seconds
+static UNLIMITED_FRAME_TIME: f32 = 1.0 / 30.0;
+static MAX_UNLIMITED_TICKS: u32 = 1000;
+// Cells added or removed on each side by the in-app resize keys
+static RESIZE_STEP: i32 = 10;
+// Camera zoom factor applied per mouse wheel notch
+static ZOOM_STEP: f32 = 1.25;
+// Ticks moved by the far rewind and forward keys
+static SCRUB_FAR_STEP: i64 = 10;
+// Most cells the bucket tool fills at once, so a misclick in open space
+// doesn't flood the world
+static BUCKET_LIMIT: usize = 20_000;
+// Largest brush, as the radius in cells plus one
+static MAX_BRUSH_SIZE: i32 = 16;
 
 struct EventLoopContext {
     program_epoch: SystemTime,
-    prev_tick: u32,
+    clock: TickClock,
+    // Ticks the step many action runs
+    step_ticks: u32,
     depression: Option<MouseButton>,
     is_paused: bool,
     draw_type_index: usize,
     draw_types: Vec<ParticleType>,
+    tool: Tool,
+    // 1 paints a single cell, bigger sizes a circle that many cells across
+    // from the centre
+    brush_size: i32,
+    // Grid cell a shape or selection drag started at
+    drag_start: Option<(i32, i32)>,
+    // Opposite corners of the selected rectangle
+    selection: Option<((i32, i32), (i32, i32))>,
+    bucket_preview: Option<BucketPreview>,
+    clipboard: Option<SaveState>,
+    // Stamp the next stamp action pastes
+    stamp_index: usize,
     save_filepath: String,
+    resize_anchor: Anchor,
+    profiler: Profiler,
+    // Save slot used by the slot save and load actions
+    save_slot: u8,
+    autosaver: Autosaver,
+    record_path: Option<String>,
+    recorder: Option<Recorder>,
+    timeline: Timeline,
+    exporter: Option<Exporter>,
+    export_every: u64,
+    export_scale: u32,
+}
+
+// The bucket's region under the mouse, kept until the mouse moves to another
+// cell or the world changes
+struct BucketPreview {
+    cell: (i32, i32),
+    tick: u64,
+    region: Vec<(i32, i32)>,
 }
 
 impl EventLoopContext {
-    fn new(save_filepath: String) -> EventLoopContext {
+    fn new(
+        save_filepath: String,
+        resize_anchor: Anchor,
+        autosaver: Autosaver,
+        record_path: Option<String>,
+        timeline: Timeline,
+        clock: TickClock,
+        step_ticks: u32
+    ) -> EventLoopContext {
         let draw_types = vec!(
             ParticleType::Water,
             ParticleType::Sand,
@@ -62,78 +125,422 @@ impl EventLoopContext {
 
         EventLoopContext {
             program_epoch: SystemTime::now(),
-            prev_tick: 0,
+            clock,
+            step_ticks,
             depression: None, // :)
             is_paused: false,
             draw_type_index: 0,
             draw_types: draw_types,
+            tool: Tool::Brush,
+            brush_size: 1,
+            drag_start: None,
+            selection: None,
+            bucket_preview: None,
+            clipboard: None,
+            stamp_index: 0,
             save_filepath: save_filepath,
+            resize_anchor,
+            profiler: Profiler::new(),
+            save_slot: 1,
+            autosaver,
+            record_path,
+            recorder: None,
+            timeline,
+            exporter: None,
+            export_every: 1,
+            export_scale: 1,
         }
     }
 }
 
 fn insert_particle(
-    grid: &mut ParticleGrid,
+    grid: &ParticleGrid,
+    x: i32,
+    y: i32,
     context: &RenderContext,
     p_type: &ParticleType
-) {
-    edit_particle(grid, context, |_| {
-        Particle {
-            p_type: p_type.clone(),
-            fill_ratio: context.max_fill,
+) -> Option<Input> {
+    let (x, y, particle) = edit_cell(grid, x, y, |p| {
+        // Keep the shade of particles we're painting over so they don't flicker
+        if p.p_type == *p_type {
+            Particle {
+                fill_ratio: context.max_fill,
+                ..p.clone()
+            }
+        } else {
+            Particle::new(*p_type, context.max_fill)
         }
-    });
+    })?;
+
+    Some(Input::Paint { x, y, particle })
 }
 
-fn edit_particle<F>(grid: &mut ParticleGrid, context: &RenderContext, edit_func: F) where
+// The particle under the mouse after edit_func, if that changes anything
+fn edit_particle<F>(grid: &ParticleGrid, context: &RenderContext, edit_func: F) -> Option<(i32, i32, Particle)> where
     F: Fn(&Particle) -> Particle
 {
-    let x = (context.mouse_x as f32 / context.scale) as i32;
-    let y = (context.mouse_y as f32 / context.scale) as i32;
+    let (x, y) = context.mouse_grid_pos();
+    edit_cell(grid, x, y, edit_func)
+}
+
+fn edit_cell<F>(grid: &ParticleGrid, x: i32, y: i32, edit_func: F) -> Option<(i32, i32, Particle)> where
+    F: Fn(&Particle) -> Particle
+{
+    if !grid.in_bounds(x, y) {
+        return None;
+    }
 
-    if grid.in_bounds(x, y) {
-        grid.set(x, y, edit_func(grid.get(x, y)));
+    let old = grid.get(x, y);
+    let new = edit_func(old);
+
+    if new == *old {
+        None
+    } else {
+        Some((x, y, new))
     }
 }
 
-pub fn main() -> Result<(), String> {
-    let opts: Opts = Opts::parse();
+// The cells the bucket would fill if clicked now. Err if there are too many.
+fn bucket_region(grid: &ParticleGrid, context: &RenderContext) -> Result<Vec<(i32, i32)>, String> {
+    let (x, y) = context.mouse_grid_pos();
+
+    grid.connected_region(x, y, BUCKET_LIMIT)
+        .ok_or_else(|| format!("Region is over {} cells, not filling", BUCKET_LIMIT))
+}
+
+// Fill the region under the mouse with the current material, one paint input
+// per cell that changes
+fn bucket_fill(elc: &mut EventLoopContext, physics: &mut Physics, context: &RenderContext) -> Option<String> {
+    let region = match bucket_region(physics.get_grid(), context) {
+        Ok(region) => region,
+        Err(err)   => return Some(err),
+    };
 
-    let (grid, max_fill) = match (opts.file_path.clone(), opts.width, opts.height) {
-        (Some(fp), _, _) => {
-            let state = SaveState::load(fp);
-            (state.grid, state.max_fill)
+    paint_cells(&region, elc, physics, context);
+    None
+}
+
+// The cells the shape being dragged out would cover if released now
+fn shape_cells(elc: &EventLoopContext, context: &RenderContext) -> Vec<(i32, i32)> {
+    match (elc.tool, elc.drag_start) {
+        (Tool::Shape(shape), Some(start)) => shape.cells(start, context.mouse_grid_pos()),
+        _ => Vec::new(),
+    }
+}
+
+// The cells under the brush
+fn brush_cells(elc: &EventLoopContext, context: &RenderContext) -> Vec<(i32, i32)> {
+    let (x, y) = context.mouse_grid_pos();
+    Shape::FilledCircle.cells((x, y), (x + elc.brush_size - 1, y))
+}
+
+fn select_material(index: usize, elc: &mut EventLoopContext, context: &mut RenderContext) {
+    elc.draw_type_index = index % elc.draw_types.len();
+    context.draw_type = elc.draw_types[elc.draw_type_index];
+}
+
+fn select_tool(tool: Tool, elc: &mut EventLoopContext) -> String {
+    elc.tool = tool;
+    elc.drag_start = None;
+    format!("{} tool", tool.name())
+}
+
+fn resize_brush(step: i32, elc: &mut EventLoopContext) -> String {
+    elc.brush_size = (elc.brush_size + step).clamp(1, MAX_BRUSH_SIZE);
+    format!("Brush size {}", elc.brush_size)
+}
+
+fn click_toolbar(item: ToolbarItem, elc: &mut EventLoopContext, context: &mut RenderContext) -> Option<String> {
+    match item {
+        ToolbarItem::Material(index) => {
+            select_material(index, elc, context);
+            None
+        },
+        ToolbarItem::NextTool     => Some(select_tool(elc.tool.next(), elc)),
+        ToolbarItem::BrushSmaller => Some(resize_brush(-1, elc)),
+        ToolbarItem::BrushBigger  => Some(resize_brush(1, elc)),
+        ToolbarItem::Slower       => {
+            elc.clock.slower();
+            Some(format!("Speed {}", elc.clock.speed_label()))
         },
-        (_, Some(width), Some(height)) => {
-            (ParticleGrid::new(width, height), opts.max_fill)
+        ToolbarItem::Faster       => {
+            elc.clock.faster();
+            Some(format!("Speed {}", elc.clock.speed_label()))
         },
+    }
+}
+
+fn toolbar_state(elc: &EventLoopContext, context: &RenderContext) -> ToolbarState {
+    ToolbarState {
+        materials: elc.draw_types.clone(),
+        selected: elc.draw_type_index,
+        colors: context.palette.colors,
+        tool: elc.tool,
+        brush_size: elc.brush_size,
+        speed: elc.clock.speed_label(),
+        width: context.win_width,
+    }
+}
+
+// What the renderer highlights for the current tool
+fn preview_cells(elc: &mut EventLoopContext, physics: &mut Physics, context: &RenderContext) -> Vec<(i32, i32)> {
+    match (elc.tool, elc.drag_start, elc.selection) {
+        (Tool::Bucket, _, _)   => bucket_preview(elc, physics, context),
+        (Tool::Shape(_), _, _) => shape_cells(elc, context),
+        (Tool::Select, Some(start), _) => Shape::Rect.cells(start, context.mouse_grid_pos()),
+        (Tool::Select, None, Some((start, end))) => Shape::Rect.cells(start, end),
+        (Tool::Paste, _, _) => paste_cells(elc, context).into_iter().map(|(x, y, _)| (x, y)).collect(),
+        // A single cell already has the cursor box around it
+        (Tool::Brush, _, _) if elc.brush_size > 1 => brush_cells(elc, context),
+        _ => Vec::new(),
+    }
+}
+
+fn bucket_preview(elc: &mut EventLoopContext, physics: &mut Physics, context: &RenderContext) -> Vec<(i32, i32)> {
+    let cell = context.mouse_grid_pos();
+    let tick = physics.get_tick();
+
+    match &elc.bucket_preview {
+        Some(preview) if preview.cell == cell && preview.tick == tick => preview.region.clone(),
         _ => {
-            return Err("Must specify a file path, or a height&width".to_string());
-        }
+            let region = bucket_region(physics.get_grid(), context).unwrap_or_default();
+            elc.bucket_preview = Some(BucketPreview { cell, tick, region: region.clone() });
+            region
+        },
+    }
+}
+
+// The selection clipped to the world, as its left, top, width and height
+fn selection_rect(elc: &EventLoopContext, grid: &ParticleGrid) -> Option<(i32, i32, i32, i32)> {
+    let ((x0, y0), (x1, y1)) = elc.selection?;
+
+    let left = x0.min(x1).max(0);
+    let top = y0.min(y1).max(0);
+    let right = x0.max(x1).min(grid.width - 1);
+    let bottom = y0.max(y1).min(grid.height - 1);
+
+    if left > right || top > bottom {
+        None
+    } else {
+        Some((left, top, right - left + 1, bottom - top + 1))
+    }
+}
+
+// Copy the selection to the clipboard, and erase it if cutting
+fn copy_selection(cut: bool, elc: &mut EventLoopContext, physics: &mut Physics, context: &RenderContext) -> String {
+    let (left, top, width, height) = match selection_rect(elc, physics.get_grid()) {
+        Some(rect) => rect,
+        None       => return "Nothing selected".to_string(),
     };
 
-    let save_filepath = match opts.file_path {
-        Some(fp) => fp,
-        None     => "save.mp".to_string()
+    elc.clipboard = Some(SaveState {
+        grid: physics.get_grid().cropped(left, top, width, height),
+        max_fill: context.max_fill,
+    });
+
+    if cut {
+        for y in top..top + height {
+            for x in left..left + width {
+                if !physics.get_grid().is_empty(x, y) {
+                    apply_input(Input::Erase { x, y }, elc, physics);
+                }
+            }
+        }
+    }
+
+    format!("{} {}x{}", if cut { "Cut" } else { "Copied" }, width, height)
+}
+
+// The clipboard's particles centred on the mouse. Empty cells in it leave
+// the world alone.
+fn paste_cells(elc: &EventLoopContext, context: &RenderContext) -> Vec<(i32, i32, Particle)> {
+    let clipboard = match &elc.clipboard {
+        Some(clipboard) => clipboard,
+        None            => return Vec::new(),
     };
 
-    let elc = EventLoopContext::new(save_filepath);
+    let grid = stamps::rescale_fill(&clipboard.grid, clipboard.max_fill, context.max_fill);
+    let (mouse_x, mouse_y) = context.mouse_grid_pos();
+    let (left, top) = (mouse_x - grid.width / 2, mouse_y - grid.height / 2);
 
-    run(elc, grid, max_fill);
-    Ok(())
+    let mut cells = Vec::new();
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let particle = grid.get(x, y);
+
+            if particle.p_type != ParticleType::Empty {
+                cells.push((left + x, top + y, particle.clone()));
+            }
+        }
+    }
+
+    cells
 }
 
-fn run(mut elc: EventLoopContext, grid: ParticleGrid, max_fill: u8) {
-    let mut context = RenderContext::new(&grid, max_fill);
-    let mut physics = Physics::new(grid, max_fill);
+fn paste(elc: &mut EventLoopContext, physics: &mut Physics, context: &RenderContext) {
+    for (x, y, particle) in paste_cells(elc, context) {
+        if let Some((x, y, particle)) = edit_cell(physics.get_grid(), x, y, |_| particle.clone()) {
+            apply_input(Input::Paint { x, y, particle }, elc, physics);
+        }
+    }
+}
+
+fn transform_clipboard<F>(elc: &mut EventLoopContext, transform: F) where
+    F: Fn(&ParticleGrid) -> ParticleGrid
+{
+    if let Some(clipboard) = &mut elc.clipboard {
+        clipboard.grid = transform(&clipboard.grid);
+    }
+}
+
+fn save_stamp(elc: &EventLoopContext) -> String {
+    match &elc.clipboard {
+        Some(clipboard) => match stamps::save(clipboard) {
+            Ok(path) => format!("Saved stamp {}", path),
+            Err(err) => err,
+        },
+        None => "Nothing to save, copy something first".to_string(),
+    }
+}
+
+// Load the next stamp in the library into the clipboard, ready to paste
+fn next_stamp(elc: &mut EventLoopContext) -> String {
+    let paths = stamps::list();
+
+    if paths.is_empty() {
+        return "No stamps saved yet".to_string();
+    }
+
+    let path = paths[elc.stamp_index % paths.len()].clone();
+    elc.stamp_index = (elc.stamp_index + 1) % paths.len();
+
+    match SaveState::load(path.clone()) {
+        Ok(stamp) => {
+            elc.clipboard = Some(stamp);
+            elc.tool = Tool::Paste;
+            format!("Stamp {}", path)
+        },
+        Err(err) => err,
+    }
+}
+
+// Paint the current material into every cell it would change, the same way
+// the brush does
+fn paint_cells(cells: &[(i32, i32)], elc: &mut EventLoopContext, physics: &mut Physics, context: &RenderContext) {
+    for &(x, y) in cells {
+        if let Some(input) = insert_particle(physics.get_grid(), x, y, context, &context.draw_type) {
+            apply_input(input, elc, physics);
+        }
+    }
+}
+
+// Every input that changes the world goes through here so it can be recorded
+fn apply_input(input: Input, elc: &mut EventLoopContext, physics: &mut Physics) {
+    if let Some(recorder) = &mut elc.recorder {
+        recorder.record(physics.get_tick(), input.clone());
+    }
+
+    elc.bucket_preview = None;
+
+    match input {
+        Input::Paint { x, y, .. } | Input::Erase { x, y } | Input::SetFill { x, y, .. } => elc.timeline.edit(x, y),
+        _ => {},
+    }
+
+    input.apply(physics);
+}
+
+pub fn main() -> Result<(), String> {
+    let opts: Opts = Opts::parse();
+
+    match opts.command {
+        Command::Run(opts)     => run_interactive(opts),
+        Command::Sim(opts)     => commands::sim(opts),
+        Command::Convert(opts) => commands::convert(opts),
+        Command::Info(opts)    => commands::info(opts),
+        Command::Bench(opts)   => commands::bench(opts),
+        Command::Replay(opts)  => commands::replay(opts),
+    }
+}
+
+fn run_interactive(opts: RunOpts) -> Result<(), String> {
+    let (grid, max_fill) = opts.world.load_world()?;
+
+    if !opts.tick_time.is_finite() || opts.tick_time <= 0.0 {
+        return Err(format!("--tick-time must be more than 0, got {}", opts.tick_time));
+    }
+
+    if opts.step_ticks < 1 {
+        return Err("--step-ticks must be at least 1".to_string());
+    }
+
+    if opts.autosave_keep < 1 {
+        return Err("--autosave-keep must be at least 1".to_string());
+    }
+
+    let save_filepath = match (opts.save_to, &opts.world.load) {
+        (Some(path), _)    => path,
+        (None, Some(path)) => path.clone(),
+        (None, None)       => "save.mp".to_string(),
+    };
+
+    let palette = match opts.palette {
+        Some(name) => Palette::load(&name)?,
+        None       => Palette::default(),
+    };
+
+    let bindings = KeyBindings::load(opts.bindings.as_deref())?;
+
+    let autosaver = Autosaver::new(autosave::AUTOSAVE_DIR, opts.autosave_interval, opts.autosave_keep);
+
+    let timeline = Timeline::new(&grid, 0, opts.history);
+
+    let mut elc = EventLoopContext::new(
+        save_filepath,
+        opts.world.anchor,
+        autosaver,
+        opts.record_input,
+        timeline,
+        TickClock::new(opts.tick_time),
+        opts.step_ticks
+    );
+    elc.export_every = opts.export_every;
+    elc.export_scale = opts.export_scale;
 
+    run(elc, grid, max_fill, palette, bindings)
+}
+
+fn run(
+    mut elc: EventLoopContext,
+    grid: ParticleGrid,
+    max_fill: u8,
+    palette: Palette,
+    bindings: KeyBindings
+) -> Result<(), String> {
     let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string()).unwrap();
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
+    let display_mode = video_subsystem.desktop_display_mode(0).unwrap();
+
+    let mut context = RenderContext::new(
+        &grid,
+        max_fill,
+        display_mode.w as u32,
+        display_mode.h as u32
+    );
+    context.palette = palette;
+    let mut physics = Physics::new(grid, max_fill);
+
+    if elc.record_path.is_some() {
+        elc.recorder = Some(Recorder::start(&mut physics, max_fill));
+    }
+
     let window = video_subsystem.window("MagicPixel", context.win_width, context.win_height)
         .position_centered()
         .opengl()
+        .resizable()
         .build()
         .unwrap();
 
@@ -141,6 +548,7 @@ fn run(mut elc: EventLoopContext, grid: ParticleGrid, max_fill: u8) {
 
     let (debug_x, debug_y) = window.position();
     let mut debug_window = DebugWindow::new(debug_x, debug_y, &video_subsystem, &ttf_context);
+    let mut toolbar = Toolbar::new(&ttf_context)?;
 
     let mut renderer = GlslRenderer::new(
         "assets/identity.vert".to_string(),
@@ -151,66 +559,262 @@ fn run(mut elc: EventLoopContext, grid: ParticleGrid, max_fill: u8) {
     );
 
     let mut event_pump = sdl_context.event_pump().unwrap();
+    let keyboard = sdl_context.keyboard();
 
     'running: loop {
         let events: Vec<Event> = event_pump.poll_iter().collect();
 
         for event in events {
             match event {
-                Event::Quit {..} |
-                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                Event::Quit {..} => {
                     break 'running
                 },
-                Event::KeyDown { keycode: Some(Keycode::P), .. } => {
-                    elc.is_paused = !elc.is_paused;
-                },
-                Event::KeyDown { keycode: Some(Keycode::S), .. } => {
-                    let state = SaveState {
-                        grid: physics.get_grid().as_ref().clone(),
-                        max_fill: max_fill,
-                    };
-                    state.save(elc.save_filepath.clone());
-                },
-                Event::KeyDown { keycode: Some(Keycode::Space), .. } => {
-                    physics.update();
-                },
-                Event::KeyDown { keycode: Some(Keycode::Comma), .. } => {
-                    elc.draw_type_index = (elc.draw_type_index + 1) % elc.draw_types.len();
-                    context.draw_type = elc.draw_types.get(elc.draw_type_index).unwrap().clone();
+                Event::KeyDown { keycode: Some(keycode), keymod, .. } => {
+                    match bindings.action_for(keycode, keymod) {
+                        Some(Action::Quit) => {
+                            break 'running
+                        },
+                        Some(Action::TogglePause) => {
+                            elc.is_paused = !elc.is_paused;
+                            apply_input(Input::Pause(elc.is_paused), &mut elc, &mut physics);
+                        },
+                        Some(Action::Save) => {
+                            let status = save_world(elc.save_filepath.clone(), &mut physics, &context);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::SaveAs) => {
+                            let status = save_world(save_state::timestamped_path(), &mut physics, &context);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::SaveSlot) => {
+                            let path = save_state::slot_path(elc.save_slot);
+                            let status = save_world(path, &mut physics, &context);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::LoadSlot) => {
+                            let path = save_state::slot_path(elc.save_slot);
+                            let status = load_world(path, &mut elc, &mut physics, &mut context, &mut renderer);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::NextSlot) => {
+                            elc.save_slot = elc.save_slot % save_state::SLOT_COUNT + 1;
+                            debug_window.set_status(format!("Slot {} selected", elc.save_slot));
+                        },
+                        Some(Action::Step) => {
+                            if let Some(status) = step(1, &mut elc, &mut physics, &context) {
+                                debug_window.set_status(status);
+                            }
+                        },
+                        Some(Action::StepMany) => {
+                            let ticks = elc.step_ticks;
+                            if let Some(status) = step(ticks, &mut elc, &mut physics, &context) {
+                                debug_window.set_status(status);
+                            }
+                        },
+                        Some(Action::Faster) => {
+                            elc.clock.faster();
+                            debug_window.set_status(format!("Speed {}", elc.clock.speed_label()));
+                        },
+                        Some(Action::Slower) => {
+                            elc.clock.slower();
+                            debug_window.set_status(format!("Speed {}", elc.clock.speed_label()));
+                        },
+                        Some(Action::RewindTick) => {
+                            let status = scrub(-1, &mut elc, &mut physics);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::ForwardTick) => {
+                            let status = scrub(1, &mut elc, &mut physics);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::RewindFar) => {
+                            let status = scrub(-SCRUB_FAR_STEP, &mut elc, &mut physics);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::ForwardFar) => {
+                            let status = scrub(SCRUB_FAR_STEP, &mut elc, &mut physics);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::ToggleExport) => {
+                            let status = toggle_export(&mut elc, &mut physics, &context);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::Copy) => {
+                            let status = copy_selection(false, &mut elc, &mut physics, &context);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::Cut) => {
+                            let status = copy_selection(true, &mut elc, &mut physics, &context);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::Paste) => {
+                            if elc.clipboard.is_some() {
+                                elc.tool = Tool::Paste;
+                                debug_window.set_status("Click to paste".to_string());
+                            } else {
+                                debug_window.set_status("Nothing to paste".to_string());
+                            }
+                        },
+                        Some(Action::RotateClipboard) => {
+                            transform_clipboard(&mut elc, |grid| grid.rotated());
+                        },
+                        Some(Action::MirrorClipboard) => {
+                            transform_clipboard(&mut elc, |grid| grid.flipped_horizontally());
+                        },
+                        Some(Action::FlipClipboard) => {
+                            transform_clipboard(&mut elc, |grid| grid.flipped_vertically());
+                        },
+                        Some(Action::SaveStamp) => {
+                            debug_window.set_status(save_stamp(&elc));
+                        },
+                        Some(Action::NextStamp) => {
+                            let status = next_stamp(&mut elc);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::GrowWorld) => {
+                            resize_world(RESIZE_STEP, &mut elc, &mut physics, &mut context, &mut renderer);
+                        },
+                        Some(Action::ShrinkWorld) => {
+                            resize_world(-RESIZE_STEP, &mut elc, &mut physics, &mut context, &mut renderer);
+                        },
+                        Some(Action::ToggleGridLines) => {
+                            context.show_grid_lines = !context.show_grid_lines;
+                        },
+                        Some(Action::RenderNormal) => {
+                            context.render_mode = RenderMode::Normal;
+                        },
+                        Some(Action::RenderFillRatio) => {
+                            context.render_mode = RenderMode::FillRatio;
+                        },
+                        Some(Action::RenderChangedCells) => {
+                            context.render_mode = RenderMode::ChangedCells;
+                        },
+                        Some(Action::MaskWater) => {
+                            context.render_mode = RenderMode::TypeMask(ParticleType::Water);
+                        },
+                        Some(Action::MaskSand) => {
+                            context.render_mode = RenderMode::TypeMask(ParticleType::Sand);
+                        },
+                        Some(Action::MaskWood) => {
+                            context.render_mode = RenderMode::TypeMask(ParticleType::Wood);
+                        },
+                        Some(Action::NextMaterial) => {
+                            select_material(elc.draw_type_index + 1, &mut elc, &mut context);
+                        },
+                        Some(Action::NextTool) => {
+                            let status = select_tool(elc.tool.next(), &mut elc);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::BrushBigger) => {
+                            let status = resize_brush(1, &mut elc);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::BrushSmaller) => {
+                            let status = resize_brush(-1, &mut elc);
+                            debug_window.set_status(status);
+                        },
+                        Some(Action::ToggleHelp) => {
+                            debug_window.toggle_help(&bindings);
+                        },
+                        None => {}
+                    }
                 },
-                Event::MouseMotion { x, y , window_id, .. } => {
+                Event::MouseMotion { x, y, xrel, yrel, window_id, .. } => {
                     if window_id == main_window_id {
                         context.mouse_x = x;
                         context.mouse_y = y;
+
+                        if elc.depression == Some(MouseButton::Middle) {
+                            context.pan(xrel, yrel);
+                        }
                     }
                 },
                 Event::MouseButtonDown { x, y , window_id, mouse_btn, .. } => {
                     context.mouse_x = x;
                     context.mouse_y = y;
 
-                    if window_id == main_window_id {
+                    if window_id == main_window_id && context.mouse_over_toolbar() {
+                        if mouse_btn == MouseButton::Left {
+                            if let Some(item) = toolbar.item_at(x, y) {
+                                if let Some(status) = click_toolbar(item, &mut elc, &mut context) {
+                                    debug_window.set_status(status);
+                                }
+                            }
+                        }
+                    } else if window_id == main_window_id {
                         elc.depression = Some(mouse_btn);
+
+                        match (mouse_btn, elc.tool) {
+                            (MouseButton::Left, Tool::Bucket) => {
+                                if let Some(status) = bucket_fill(&mut elc, &mut physics, &context) {
+                                    debug_window.set_status(status);
+                                }
+                            },
+                            (MouseButton::Left, Tool::Shape(_)) | (MouseButton::Left, Tool::Select) => {
+                                elc.drag_start = Some(context.mouse_grid_pos());
+                            },
+                            (MouseButton::Left, Tool::Paste) => {
+                                paste(&mut elc, &mut physics, &context);
+                            },
+                            // Right clicking during a drag drops it
+                            (MouseButton::Right, _) if elc.drag_start.is_some() => {
+                                elc.drag_start = None;
+                                elc.depression = None;
+                            },
+                            _ => {},
+                        }
                     }
                 },
-                Event::MouseButtonUp { window_id, .. } => {
+                Event::MouseButtonUp { window_id, mouse_btn, .. } => {
                     if window_id == main_window_id {
                         elc.depression = None;
+
+                        if mouse_btn == MouseButton::Left {
+                            match (elc.tool, elc.drag_start) {
+                                (Tool::Select, Some(start)) => {
+                                    elc.selection = Some((start, context.mouse_grid_pos()));
+                                },
+                                (Tool::Shape(_), Some(_)) => {
+                                    let cells = shape_cells(&elc, &context);
+                                    paint_cells(&cells, &mut elc, &mut physics, &context);
+                                },
+                                _ => {},
+                            }
+                            elc.drag_start = None;
+                        }
                     }
                 },
+                Event::MouseWheel { y, .. } if keyboard.mod_state().intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    context.zoom_at_mouse(ZOOM_STEP.powi(y));
+                },
                 Event::MouseWheel { y, .. } => {
                     // wow impressive
-                    edit_particle(physics.get_grid(), &context, |p| {
+                    let edit = edit_particle(physics.get_grid(), &context, |p| {
                         if p.p_type == ParticleType::Empty {
                             p.clone()
                         } else {
                             let new_fill_ratio = p.fill_ratio as i32 + y;
 
                             Particle {
-                                fill_ratio: max(1, min(max_fill, new_fill_ratio as u8)),
+                                fill_ratio: max(1, min(context.max_fill, new_fill_ratio as u8)),
                                 ..p.clone()
                             }
                         }
                     });
+
+                    if let Some((x, y, particle)) = edit {
+                        let input = Input::SetFill { x, y, fill_ratio: particle.fill_ratio };
+                        apply_input(input, &mut elc, &mut physics);
+                    }
+                },
+                // Sent for user resizes as well as our own set_size calls
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(width, height),
+                    window_id,
+                    ..
+                } if window_id == main_window_id => {
+                    context.set_window_size(width as u32, height as u32);
+                    renderer.resize_viewport(&context);
                 },
                 Event::Window { win_event: WindowEvent::Leave, .. } => {
                     elc.depression = None;
@@ -226,46 +830,271 @@ fn run(mut elc: EventLoopContext, grid: ParticleGrid, max_fill: u8) {
             }
         }
 
-        handle_depression(&context, &elc, &mut physics); // Therapy
+        handle_depression(&context, &mut elc, &mut physics); // Therapy
 
         let curr_time = get_current_time(&elc);
-        tick_physics(curr_time, &mut elc, &mut physics);
-        debug_window.render(&physics.get_grid(), &context, curr_time);
+        if let Some(status) = tick_physics(curr_time, &mut elc, &mut physics, &context) {
+            debug_window.set_status(status);
+        }
+
+        if elc.autosaver.is_due(curr_time, physics.get_tick()) {
+            let state = current_state(&mut physics, &context);
+
+            if let Err(err) = elc.autosaver.save(&state, curr_time, physics.get_tick()) {
+                debug_window.set_status(err);
+            }
+        }
+        debug_window.render(
+            &physics.get_grid(),
+            &context,
+            renderer.get_shader_error(),
+            &elc.profiler,
+            &elc.clock,
+            elc.is_paused
+        );
+
+        let render_start = SystemTime::now();
+
+        if context.render_mode == RenderMode::ChangedCells {
+            renderer.set_changed_cells(physics.get_changed_last_tick());
+        }
+
+        let preview = preview_cells(&mut elc, &mut physics, &context);
+        renderer.set_preview(&preview, &context);
+
+        match toolbar.update(toolbar_state(&elc, &context)) {
+            Ok(Some(pixels)) => renderer.set_toolbar(pixels),
+            Ok(None)         => {},
+            Err(err)         => debug_window.set_status(err),
+        }
 
         renderer.render(&physics.get_grid(), &context);
+
+        elc.profiler.record_render(curr_time, seconds_since(render_start));
+    }
+
+    if let (Some(recorder), Some(path)) = (elc.recorder.take(), &elc.record_path) {
+        recorder.finish(&mut physics).save(path)?;
+        println!("Saved recording {}", path);
     }
+
+    Ok(())
 }
 
-fn handle_depression(context: &RenderContext, elc: &EventLoopContext, physics: &mut Physics) {
+fn current_state(physics: &mut Physics, context: &RenderContext) -> SaveState {
+    SaveState {
+        grid: physics.get_grid().as_ref().clone(),
+        max_fill: context.max_fill,
+    }
+}
+
+// Returns a status message for the debug window
+fn save_world(path: String, physics: &mut Physics, context: &RenderContext) -> String {
+    match current_state(physics, context).save(path.clone()) {
+        Ok(())   => format!("Saved {}", path),
+        Err(err) => err,
+    }
+}
+
+// Swap a saved world into the running simulation
+fn load_world(
+    path: String,
+    elc: &mut EventLoopContext,
+    physics: &mut Physics,
+    context: &mut RenderContext,
+    renderer: &mut GlslRenderer
+) -> String {
+    let state = match SaveState::load(path.clone()) {
+        Ok(state) => state,
+        Err(err)  => return err,
+    };
+
+    physics.replace_grid(state.grid, state.max_fill);
+    elc.bucket_preview = None;
+    let tick = physics.get_tick();
+    elc.timeline.reset(physics.get_grid(), tick);
+    context.max_fill = state.max_fill;
+    context.resize(physics.get_grid());
+    renderer.resize(context);
+
+    // A recording covers a single starting world, so start over from this one
+    if elc.recorder.is_some() {
+        elc.recorder = Some(Recorder::start(physics, state.max_fill));
+        return format!("Loaded {}, recording restarted", path);
+    }
+
+    format!("Loaded {}", path)
+}
+
+// Grow (or crop, for negative steps) the world by `step` cells on each side
+fn resize_world(
+    step: i32,
+    elc: &mut EventLoopContext,
+    physics: &mut Physics,
+    context: &mut RenderContext,
+    renderer: &mut GlslRenderer
+) {
+    let grid = physics.get_grid();
+    let width = grid.width + 2 * step;
+    let height = grid.height + 2 * step;
+
+    if width < 1 || height < 1 {
+        return;
+    }
+
+    let input = Input::Resize { width, height, anchor: elc.resize_anchor };
+    apply_input(input, elc, physics);
+    let tick = physics.get_tick();
+    elc.timeline.reset(physics.get_grid(), tick);
+    context.resize(physics.get_grid());
+    renderer.resize(context);
+}
+
+// Bookkeeping after every physics update. Returns a status message if
+// exporting had to stop.
+fn after_tick(elc: &mut EventLoopContext, physics: &mut Physics, context: &RenderContext) -> Option<String> {
+    let tick = physics.get_tick();
+    let written = physics.get_written_last_tick().to_vec();
+    elc.timeline.record(physics.get_grid(), &written, tick);
+
+    let exporter = elc.exporter.as_mut()?;
+
+    match exporter.capture(physics.get_grid(), &context.palette, context.max_fill, tick) {
+        Ok(())   => None,
+        Err(err) => {
+            elc.exporter = None;
+            Some(err)
+        },
+    }
+}
+
+// Start exporting to a new GIF, or finish the current one
+fn toggle_export(elc: &mut EventLoopContext, physics: &mut Physics, context: &RenderContext) -> String {
+    if let Some(exporter) = elc.exporter.take() {
+        // Dropping the exporter finishes the file
+        return format!("Exported {} frames to {}", exporter.frames(), exporter.path());
+    }
+
+    let path = export::timestamped_path();
+
+    let tick_time = elc.clock.tick_time();
+
+    match Exporter::create(&path, physics.get_grid(), elc.export_every, elc.export_scale, tick_time) {
+        Ok(mut exporter) => {
+            let tick = physics.get_tick();
+            let _ = exporter.capture(physics.get_grid(), &context.palette, context.max_fill, tick);
+            elc.exporter = Some(exporter);
+            format!("Exporting to {}", path)
+        },
+        Err(err) => err,
+    }
+}
+
+// Move through the timeline by `ticks`, negative for back in time. This
+// pauses the simulation. Resuming from an earlier tick throws away the
+// ticks after it.
+fn scrub(ticks: i64, elc: &mut EventLoopContext, physics: &mut Physics) -> String {
+    // Recordings can't go back in time, and starting over would lose it
+    if elc.recorder.is_some() {
+        return "Can't rewind while recording input".to_string();
+    }
+
+    if !elc.is_paused {
+        elc.is_paused = true;
+        apply_input(Input::Pause(true), elc, physics);
+    }
+
+    let earliest = elc.timeline.earliest();
+    let latest = elc.timeline.latest();
+
+    if physics.get_tick() == latest {
+        elc.timeline.amend(physics.get_grid());
+    }
+
+    let target = (physics.get_tick() as i64 + ticks).clamp(earliest as i64, latest as i64) as u64;
+
+    if target != physics.get_tick() {
+        if let Some(grid) = elc.timeline.grid_at(target) {
+            physics.rewind(grid, target);
+            elc.bucket_preview = None;
+        }
+    }
+
+    format!("Tick {} of {}-{}", physics.get_tick(), earliest, latest)
+}
+
+fn handle_depression(context: &RenderContext, elc: &mut EventLoopContext, physics: &mut Physics) {
+    // Dragging onto the toolbar doesn't paint whatever is hidden under it
+    if context.mouse_over_toolbar() {
+        return;
+    }
+
     match elc.depression {
-        Some(MouseButton::Left) => {
-            let draw_type = elc.draw_types.get(elc.draw_type_index).unwrap();
-            insert_particle(physics.get_grid(), &context, &draw_type)
+        Some(MouseButton::Left) if elc.tool == Tool::Brush => {
+            let cells = brush_cells(elc, context);
+            paint_cells(&cells, elc, physics, context);
+        },
+        Some(MouseButton::Right) => {
+            for (x, y) in brush_cells(elc, context) {
+                if let Some((x, y, _)) = edit_cell(physics.get_grid(), x, y, |_| Default::default()) {
+                    apply_input(Input::Erase { x, y }, elc, physics);
+                }
+            }
         },
-        Some(MouseButton::Right) =>
-            edit_particle(physics.get_grid(), &context, |_| {
-                Default::default()
-            }),
         _ => {},
     }
 }
 
 fn get_current_time(elc: &EventLoopContext) -> f32 {
+    seconds_since(elc.program_epoch)
+}
+
+fn seconds_since(time: SystemTime) -> f32 {
     SystemTime::now()
-        .duration_since(elc.program_epoch)
-        .unwrap()
+        .duration_since(time)
+        .unwrap_or_default()
         .as_secs_f32()
 }
 
-fn tick_physics(curr_time: f32, elc: &mut EventLoopContext, physics: &mut Physics) {
-    let curr_tick = (curr_time / TICK_TIME) as u32;
+// Run `ticks` ticks straight away, whether or not the simulation is paused
+fn step(ticks: u32, elc: &mut EventLoopContext, physics: &mut Physics, context: &RenderContext) -> Option<String> {
+    let mut status = None;
 
-    if curr_tick > elc.prev_tick {
-        while elc.prev_tick < curr_tick {
-            if ! elc.is_paused {
-                physics.update();
-            }
-            elc.prev_tick += 1;
+    for _ in 0..ticks {
+        apply_input(Input::Step, elc, physics);
+        status = after_tick(elc, physics, context).or(status);
+    }
+
+    status
+}
+
+// Returns a status message if something went wrong along the way
+fn tick_physics(
+    curr_time: f32,
+    elc: &mut EventLoopContext,
+    physics: &mut Physics,
+    context: &RenderContext
+) -> Option<String> {
+    if elc.is_paused {
+        elc.clock.skip(curr_time);
+        return None;
+    }
+
+    let max_ticks = if elc.clock.is_unlimited() { MAX_UNLIMITED_TICKS } else { MAX_CATCH_UP_TICKS };
+    let ticks = elc.clock.ticks_due(curr_time, max_ticks);
+    let frame_start = SystemTime::now();
+    let mut status = None;
+
+    for _ in 0..ticks {
+        let tick_start = SystemTime::now();
+        physics.update();
+        elc.profiler.record_tick(curr_time, seconds_since(tick_start), physics.get_active_cells());
+        status = after_tick(elc, physics, context).or(status);
+
+        if elc.clock.is_unlimited() && seconds_since(frame_start) > UNLIMITED_FRAME_TIME {
+            break;
         }
     }
+
+    status
 }
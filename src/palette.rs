@@ -0,0 +1,144 @@
+use crate::config;
+use crate::grid::{Particle, ParticleType};
+
+use std::path::Path;
+
+static PALETTE_DIR: &str = "assets/palettes";
+
+pub type Rgb = [f32; 3];
+
+// Colours for each particle type, indexed by `ParticleType::id`
+#[derive(Clone, Debug)]
+pub struct Palette {
+    pub colors: [Rgb; 4],
+    // How far a particle's shade can push its colour from the base, 0 to 1
+    pub variations: [f32; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette {
+            colors: [
+                [0.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0],
+                [194.0 / 255.0, 178.0 / 255.0, 128.0 / 255.0],
+                [42.0 / 255.0, 23.0 / 255.0, 11.0 / 255.0],
+            ],
+            variations: [0.0, 0.05, 0.25, 0.3],
+        }
+    }
+}
+
+impl Palette {
+    // Load either a palette file, or a preset by name from assets/palettes
+    pub fn load(name_or_path: &str) -> Result<Palette, String> {
+        let path = if Path::new(name_or_path).is_file() {
+            name_or_path.to_string()
+        } else {
+            format!("{}/{}.palette", PALETTE_DIR, name_or_path)
+        };
+
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read palette {}: {}", path, e))?;
+
+        Palette::parse(&contents).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // One entry per line, "<type> = #rrggbb [variation]". Types that aren't
+    // listed keep their defaults.
+    pub fn parse(contents: &str) -> Result<Palette, String> {
+        let mut palette = Palette::default();
+
+        for (line_no, entry) in config::entries(contents) {
+            let err = |msg: &str| format!("line {}: {}", line_no, msg);
+
+            let (name, value) = entry.map_err(|e| err(&e))?;
+
+            let p_type = match name {
+                "empty" => ParticleType::Empty,
+                "water" => ParticleType::Water,
+                "sand"  => ParticleType::Sand,
+                "wood"  => ParticleType::Wood,
+                _       => return Err(err(&format!("unknown particle type '{}'", name))),
+            };
+
+            let mut parts = value.split_whitespace();
+
+            let color = parts.next()
+                .and_then(parse_hex_color)
+                .ok_or_else(|| err("expected a colour like #c2b280"))?;
+            palette.colors[p_type.id()] = color;
+
+            if let Some(variation) = parts.next() {
+                palette.variations[p_type.id()] = variation.parse::<f32>().ok()
+                    .filter(|v| v.is_finite())
+                    .ok_or_else(|| err("variation must be a number"))?
+                    .clamp(0.0, 1.0);
+            }
+        }
+
+        Ok(palette)
+    }
+
+    // Same colouring as grid.frag, for rendering on the CPU
+    pub fn particle_color(&self, particle: &Particle, max_fill: u8) -> [u8; 3] {
+        let id = particle.p_type.id();
+        let base = self.colors[id];
+
+        let brightness = if particle.p_type == ParticleType::Empty {
+            1.0
+        } else {
+            let fill_percent = particle.fill_ratio as f32 / max_fill as f32;
+            fill_percent * shade_factor(particle.shade, self.variations[id])
+        };
+
+        let mut rgb = [0; 3];
+        for (out, channel) in rgb.iter_mut().zip(base.iter()) {
+            *out = ((channel * brightness).clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        rgb
+    }
+
+    // The particle type whose base colour is nearest, for reading images
+    pub fn closest_type(&self, rgb: [u8; 3]) -> ParticleType {
+        let distance = |color: &Rgb| -> f32 {
+            color.iter()
+                .zip(rgb.iter())
+                .map(|(c, v)| (c * 255.0 - *v as f32).powi(2))
+                .sum()
+        };
+
+        let mut closest = ParticleType::Empty;
+        let mut closest_distance = f32::MAX;
+
+        for p_type in ParticleType::ALL.iter() {
+            let d = distance(&self.colors[p_type.id()]);
+
+            if d < closest_distance {
+                closest = *p_type;
+                closest_distance = d;
+            }
+        }
+
+        closest
+    }
+}
+
+// Brightness multiplier for a shade, centred on 1
+fn shade_factor(shade: u8, variation: f32) -> f32 {
+    1.0 + variation * (shade as f32 / 255.0 - 0.5)
+}
+
+fn parse_hex_color(hex: &str) -> Option<Rgb> {
+    let hex = hex.strip_prefix('#')?;
+
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let channel = |i: usize| {
+        u8::from_str_radix(hex.get(i..i + 2)?, 16).ok().map(|c| c as f32 / 255.0)
+    };
+
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
@@ -1,8 +1,8 @@
 use std::cmp::{min};
-use rand::Rng;
-use rand::rngs::ThreadRng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
-use crate::grid::{Grid, Particle, ParticleGrid, ParticleType};
+use crate::grid::{Anchor, Grid, Particle, ParticleGrid, ParticleType};
 
 macro_rules! random_eval {
     ($rng:expr, $x:expr, $y:expr) => {
@@ -35,21 +35,39 @@ macro_rules! random_condition {
 }
 
 pub struct Physics {
-    rng: ThreadRng,
+    // Seeded so a recorded session replays exactly. ChaCha8 gives the same
+    // numbers on every platform and rand version.
+    rng: ChaCha8Rng,
     grid: Box<ParticleGrid>,
     has_changed_grid: Grid<bool>,
+    // Every cell flagged in has_changed_grid during the last update
+    changed_last_tick: Grid<bool>,
+    // Cells flagged in changed_last_tick, counted as they're flagged
+    active_cells: usize,
+    // Index of every cell written to during the last update, maybe repeated
+    written_last_tick: Vec<usize>,
     max_fill: u8,
+    // Number of updates run so far
+    tick: u64,
 }
 
 impl Physics {
     pub fn new(grid: ParticleGrid, max_fill: u8) -> Physics {
+        Physics::with_seed(grid, max_fill, rand::random())
+    }
+
+    pub fn with_seed(grid: ParticleGrid, max_fill: u8, seed: u64) -> Physics {
         let bool_grid = Grid::new(grid.width, grid.height);
 
         Physics {
-            rng: rand::thread_rng(),
+            rng: ChaCha8Rng::seed_from_u64(seed),
             grid: Box::new(grid),
-            has_changed_grid: bool_grid,
+            has_changed_grid: bool_grid.clone(),
+            changed_last_tick: bool_grid,
+            active_cells: 0,
+            written_last_tick: Vec::new(),
             max_fill: max_fill,
+            tick: 0,
         }
     }
 
@@ -57,6 +75,72 @@ impl Physics {
         &mut self.grid
     }
 
+    pub fn get_tick(&self) -> u64 {
+        self.tick
+    }
+
+    // Restart the random sequence, e.g. at the start of a recording
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    // Swap in a different world, e.g. one loaded from a save
+    pub fn replace_grid(&mut self, grid: ParticleGrid, max_fill: u8) {
+        self.has_changed_grid = Grid::new(grid.width, grid.height);
+        self.changed_last_tick = Grid::new(grid.width, grid.height);
+        self.active_cells = 0;
+        self.written_last_tick.clear();
+        *self.grid = grid;
+        self.max_fill = max_fill;
+    }
+
+    // Go back to an earlier state of the world, from the timeline
+    pub fn rewind(&mut self, grid: ParticleGrid, tick: u64) {
+        self.replace_grid(grid, self.max_fill);
+        self.tick = tick;
+    }
+
+    pub fn resize(&mut self, width: i32, height: i32, anchor: Anchor) {
+        *self.grid = self.grid.resized(width, height, anchor);
+        self.has_changed_grid = Grid::new(width, height);
+        self.changed_last_tick = Grid::new(width, height);
+        self.active_cells = 0;
+        self.written_last_tick.clear();
+    }
+
+    pub fn get_changed_last_tick(&self) -> &Grid<bool> {
+        &self.changed_last_tick
+    }
+
+    // Every cell that can differ from before the last update
+    pub fn get_written_last_tick(&self) -> &[usize] {
+        &self.written_last_tick
+    }
+
+    // Cells that did something during the last update
+    pub fn get_active_cells(&self) -> usize {
+        self.active_cells
+    }
+
+    fn mark_changed(&mut self, x: i32, y: i32) {
+        self.has_changed_grid.set(x, y, true);
+
+        if !*self.changed_last_tick.get(x, y) {
+            self.changed_last_tick.set(x, y, true);
+            self.active_cells += 1;
+        }
+    }
+
+    fn mark_written(&mut self, x: i32, y: i32) {
+        self.written_last_tick.push((x + y * self.grid.width) as usize);
+    }
+
+    fn swap_cells(&mut self, x1: i32, y1: i32, x2: i32, y2: i32) {
+        self.grid.swap(x1, y1, x2, y2);
+        self.mark_written(x1, y1);
+        self.mark_written(x2, y2);
+    }
+
     fn try_displace_sand(&mut self, x1: i32, y1: i32, x2: i32, y2: i32) -> bool {
         if !self.grid.in_bounds(x2, y2) {
             return false;
@@ -66,7 +150,7 @@ impl Physics {
 
         if p_type == ParticleType::Water || p_type == ParticleType::Empty {
             self.flow_adjacent(x2, y2);
-            self.grid.swap(x1, y1, x2, y2);
+            self.swap_cells(x1, y1, x2, y2);
             true
         } else {
             false
@@ -87,7 +171,7 @@ impl Physics {
 
     fn try_move_sand(&mut self, x: i32, y: i32) {
         if self.try_displace_sand(x, y, x, y + 1) {
-            self.has_changed_grid.set(x, y, true);
+            self.mark_changed(x, y);
             return;
         }
 
@@ -95,7 +179,7 @@ impl Physics {
 
         if self.try_displace_sand(x, y, x + first_dir, y + 1) ||
             self.try_displace_sand(x, y, x - first_dir, y + 1) {
-            self.has_changed_grid.set(x, y, true);
+            self.mark_changed(x, y);
         }
     }
 
@@ -136,13 +220,17 @@ impl Physics {
 
                 self.grid.get_mut(slurp_x, src_y).fill_ratio -= delta;
 
+                let shade = self.grid.get(slurp_x, src_y).shade;
+
                 self.grid.set(tgt_x, tgt_y, Particle {
                     fill_ratio: delta,
                     p_type: ParticleType::Water,
-                    ..Default::default()
+                    shade,
                 });
 
-                self.has_changed_grid.set(tgt_x, tgt_y, true);
+                self.mark_changed(tgt_x, tgt_y);
+                self.mark_written(slurp_x, src_y);
+                self.mark_written(tgt_x, tgt_y);
                 break;
             }
         }
@@ -184,15 +272,15 @@ impl Physics {
         let target = self.grid.get(x, y + 1).clone();
 
         if target.p_type == ParticleType::Empty {
-            self.grid.swap(x, y, x, y + 1);
+            self.swap_cells(x, y, x, y + 1);
             self.inner_fill(lhs, rhs, x, y);
-            self.has_changed_grid.set(x, y, true);
+            self.mark_changed(x, y);
 
             // In case it's 1s all the way down
             if self.grid.is_empty(x, y) {
                 match self.find_edge(lhs, rhs, y) {
                     Some(edge_x) => {
-                        self.grid.swap(x, y, edge_x, y);
+                        self.swap_cells(x, y, edge_x, y);
                     },
                     None => {}
                 }
@@ -211,7 +299,7 @@ impl Physics {
         let source = self.grid.get(x1, y1).clone();
 
         if target.p_type == ParticleType::Empty {
-            self.grid.swap(x1, y1, x2, y2);
+            self.swap_cells(x1, y1, x2, y2);
             true
         } else if target.p_type != source.p_type {
             false
@@ -236,6 +324,9 @@ impl Physics {
             self.grid.get_mut(x1, y1).fill_ratio = new_source_fr;
             self.grid.get_mut(x2, y2).fill_ratio = new_target_fr;
         }
+
+        self.mark_written(x1, y1);
+        self.mark_written(x2, y2);
     }
 
     // Find a random non-empty edge on the left or right side
@@ -328,11 +419,14 @@ impl Physics {
             if self.grid.is_empty(x, y) {
                 self.grid.get_mut(x, y).p_type = ParticleType::Water;
                 self.grid.get_mut(x, y).fill_ratio = delta;
+                self.grid.get_mut(x, y).shade = self.grid.get(max_x, y).shade;
             } else {
                 self.grid.get_mut(x, y).fill_ratio += delta;
             }
 
-            self.has_changed_grid.set(x, y, true);
+            self.mark_changed(x, y);
+            self.mark_written(max_x, y);
+            self.mark_written(x, y);
         }
     }
 
@@ -364,6 +458,11 @@ impl Physics {
     }
 
     pub fn update(&mut self) {
+        self.tick += 1;
+        self.changed_last_tick.clear_all();
+        self.active_cells = 0;
+        self.written_last_tick.clear();
+
         for y in (0..self.grid.height).rev() {
             let mut x = 0;
 
@@ -0,0 +1,38 @@
+use crate::fps::{FpsCounter, TimingHistory};
+
+// Samples kept for the debug window's statistics and graph
+const HISTORY_SIZE: usize = 120;
+
+// Timings of the main loop, shown in the debug window
+pub struct Profiler {
+    // How long each physics tick and render took
+    pub tick_times: TimingHistory,
+    pub render_times: TimingHistory,
+    // How often physics ticks and render frames happen
+    pub tick_counter: FpsCounter,
+    pub frame_counter: FpsCounter,
+    pub active_cells: usize,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            tick_times: TimingHistory::new(HISTORY_SIZE),
+            render_times: TimingHistory::new(HISTORY_SIZE),
+            tick_counter: FpsCounter::new(HISTORY_SIZE),
+            frame_counter: FpsCounter::new(HISTORY_SIZE),
+            active_cells: 0,
+        }
+    }
+
+    pub fn record_tick(&mut self, curr_time: f32, duration: f32, active_cells: usize) {
+        self.tick_times.push(duration);
+        self.tick_counter.tick(curr_time);
+        self.active_cells = active_cells;
+    }
+
+    pub fn record_render(&mut self, curr_time: f32, duration: f32) {
+        self.render_times.push(duration);
+        self.frame_counter.tick(curr_time);
+    }
+}
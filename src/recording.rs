@@ -0,0 +1,217 @@
+use crate::grid::{Anchor, Particle, ParticleGrid};
+use crate::physics::Physics;
+use crate::save_state::SaveState;
+
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::io::Write;
+
+static RECORDING_VERSION: u8 = 1;
+
+// Everything the user can do that changes how the simulation plays out
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Input {
+    Paint { x: i32, y: i32, particle: Particle },
+    Erase { x: i32, y: i32 },
+    SetFill { x: i32, y: i32, fill_ratio: u8 },
+    Resize { width: i32, height: i32, anchor: Anchor },
+    // Only kept so the log reads like the session did. Replays go by tick
+    // numbers, so pausing doesn't change anything.
+    Pause(bool),
+    Step,
+}
+
+impl Input {
+    // Used for live input as well as replays, so both take the same path
+    pub fn apply(&self, physics: &mut Physics) {
+        match self {
+            Input::Paint { x, y, particle } => {
+                set_particle(physics.get_grid(), *x, *y, particle.clone());
+            },
+            Input::Erase { x, y } => {
+                set_particle(physics.get_grid(), *x, *y, Default::default());
+            },
+            Input::SetFill { x, y, fill_ratio } => {
+                let grid = physics.get_grid();
+
+                if grid.in_bounds(*x, *y) {
+                    let particle = Particle {
+                        fill_ratio: *fill_ratio,
+                        ..grid.get(*x, *y).clone()
+                    };
+                    grid.set(*x, *y, particle);
+                }
+            },
+            Input::Resize { width, height, anchor } => {
+                physics.resize(*width, *height, *anchor);
+            },
+            Input::Pause(_) => {},
+            Input::Step => physics.update(),
+        }
+    }
+}
+
+fn set_particle(grid: &mut ParticleGrid, x: i32, y: i32, particle: Particle) {
+    if grid.in_bounds(x, y) {
+        grid.set(x, y, particle);
+    }
+}
+
+// A starting world, the seed physics ran with and every input, stamped with
+// the number of ticks since the recording started
+#[derive(Serialize, Deserialize)]
+pub struct Recording {
+    seed: u64,
+    start: SaveState,
+    inputs: Vec<(u64, Input)>,
+    end_tick: u64,
+    // What the world looked like when recording stopped, to check replays against
+    end_grid: ParticleGrid,
+}
+
+pub struct Recorder {
+    start_tick: u64,
+    recording: Recording,
+}
+
+impl Recorder {
+    // Reseeds physics, so the recording has the whole random sequence
+    pub fn start(physics: &mut Physics, max_fill: u8) -> Recorder {
+        let seed = rand::random();
+        physics.reseed(seed);
+
+        let grid = physics.get_grid().as_ref().clone();
+
+        Recorder {
+            start_tick: physics.get_tick(),
+            recording: Recording {
+                seed,
+                start: SaveState { max_fill, grid: grid.clone() },
+                inputs: Vec::new(),
+                end_tick: 0,
+                end_grid: grid,
+            },
+        }
+    }
+
+    pub fn record(&mut self, tick: u64, input: Input) {
+        self.recording.inputs.push((tick - self.start_tick, input));
+    }
+
+    pub fn finish(mut self, physics: &mut Physics) -> Recording {
+        self.recording.end_tick = physics.get_tick() - self.start_tick;
+        self.recording.end_grid = physics.get_grid().as_ref().clone();
+        self.recording
+    }
+}
+
+impl Recording {
+    pub fn load(path: &str) -> Result<Recording, String> {
+        let contents = fs::read(path).map_err(|e| format!("Could not open {}: {}", path, e))?;
+
+        let recording: Recording = match contents.split_first() {
+            Some((v, encoded)) if *v == RECORDING_VERSION => bincode::deserialize(encoded)
+                .map_err(|e| format!("{} is corrupted: {}", path, e))?,
+            Some((v, _)) => return Err(format!("{} has unsupported recording version {}", path, v)),
+            None         => return Err(format!("{} is empty", path)),
+        };
+
+        // Resizing to nothing would panic partway through the replay
+        for (_, input) in recording.inputs.iter() {
+            if let Input::Resize { width, height, .. } = input {
+                if *width < 1 || *height < 1 {
+                    return Err(format!("{} is corrupted: resizes the world to {}x{}", path, width, height));
+                }
+            }
+        }
+
+        Ok(recording)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let encoded: Vec<u8> = bincode::serialize(&self).unwrap();
+        let save_err = |e: std::io::Error| format!("Could not save to {}: {}", path, e);
+
+        let mut file = fs::File::create(path).map_err(save_err)?;
+        file.write_all(&[RECORDING_VERSION])
+            .and_then(|_| file.write_all(&encoded))
+            .map_err(save_err)
+    }
+
+    pub fn input_count(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn end_tick(&self) -> u64 {
+        self.end_tick
+    }
+
+    // Run the inputs against a freshly seeded copy of the starting world
+    pub fn replay(&self) -> Physics {
+        let mut physics = Physics::with_seed(self.start.grid.clone(), self.start.max_fill, self.seed);
+
+        for (tick, input) in &self.inputs {
+            run_until(&mut physics, *tick);
+            input.apply(&mut physics);
+        }
+
+        run_until(&mut physics, self.end_tick);
+        physics
+    }
+
+    // Whether a replay ended up where the recorded session did
+    pub fn matches_end(&self, grid: &ParticleGrid) -> bool {
+        self.end_grid == *grid
+    }
+
+    pub fn max_fill(&self) -> u8 {
+        self.start.max_fill
+    }
+}
+
+fn run_until(physics: &mut Physics, tick: u64) {
+    while physics.get_tick() < tick {
+        physics.update();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::ParticleType;
+
+    #[test]
+    fn replay_ends_with_the_recorded_grid() {
+        let mut physics = Physics::new(ParticleGrid::new(20, 20), 8);
+        let mut recorder = Recorder::start(&mut physics, 8);
+
+        for tick in 0..60 {
+            if tick % 3 == 0 {
+                let input = Input::Paint {
+                    x: 5 + tick % 10,
+                    y: 0,
+                    particle: Particle::new(ParticleType::Water, 8),
+                };
+                recorder.record(physics.get_tick(), input.clone());
+                input.apply(&mut physics);
+            }
+
+            if tick == 30 {
+                let input = Input::Paint { x: 10, y: 1, particle: Particle::new(ParticleType::Sand, 8) };
+                recorder.record(physics.get_tick(), input.clone());
+                input.apply(&mut physics);
+
+                recorder.record(physics.get_tick(), Input::Step);
+                Input::Step.apply(&mut physics);
+            }
+
+            physics.update();
+        }
+
+        let recording = recorder.finish(&mut physics);
+        let mut replayed = recording.replay();
+
+        assert_eq!(replayed.get_tick(), recording.end_tick());
+        assert!(recording.matches_end(replayed.get_grid()));
+    }
+}
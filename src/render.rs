@@ -1,25 +1,74 @@
 extern crate gl;
 
-use crate::grid::{ParticleGrid, ParticleType};
+use crate::grid::{Grid, ParticleGrid, ParticleType};
 use crate::{RenderContext};
+use crate::toolbar::TOOLBAR_HEIGHT;
 
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 
 use std::ffi::CString;
+use std::time::SystemTime;
 
 use gl::types::{GLfloat, GLenum, GLuint, GLint, GLchar, GLsizeiptr};
 
+// What the main window shows. Everything but Normal is for debugging.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RenderMode {
+    Normal,
+    // Heat map of water fill ratios
+    FillRatio,
+    // Cells flagged in Physics::has_changed_grid during the last tick
+    ChangedCells,
+    // Only particles of the given type
+    TypeMask(ParticleType),
+}
+
+impl RenderMode {
+    // Matches the render_mode uniform in grid.frag
+    fn uniform_id(&self) -> i32 {
+        match self {
+            RenderMode::Normal       => 0,
+            RenderMode::FillRatio    => 1,
+            RenderMode::ChangedCells => 2,
+            RenderMode::TypeMask(_)  => 3,
+        }
+    }
+}
+
 pub trait Renderer {
     fn render(&mut self, grid: &ParticleGrid, context: &RenderContext);
 }
 
+// Shader storage buffer bindings, see grid.frag
+static GRID_BINDING: GLuint = 3;
+static OVERLAY_BINDING: GLuint = 4;
+static TOOLBAR_BINDING: GLuint = 5;
+
+// Bits of each cell in the overlay buffer, see grid.frag
+static OVERLAY_CHANGED: u32 = 1;
+static OVERLAY_PREVIEW: u32 = 2;
+
 pub struct GlslRenderer {
     vertex_array_id: GLuint,
     grid_buffer_id: GLuint,
+    overlay_buffer_id: GLuint,
+    toolbar_buffer_id: GLuint,
     program_id: GLuint,
     pixel_data: Vec<u32>,
+    // Per cell data for the debug render modes, uploaded next to pixel_data
+    overlay_data: Vec<u32>,
+    // Cells marked as preview in overlay_data
+    preview: Vec<(i32, i32)>,
+    // Pixels of the toolbar, kept to upload again when the window resizes
+    toolbar_data: Vec<u32>,
     canvas: Canvas<Window>,
+    vert_shader_path: String,
+    frag_shader_path: String,
+    // Newest modification time of the shader files the program was built from
+    shaders_modified: Option<SystemTime>,
+    // Compile or link log from the last failed reload
+    shader_error: Option<String>,
 }
 
 impl GlslRenderer {
@@ -41,30 +90,177 @@ impl GlslRenderer {
         // sdl::render creates a context for you, if you use a Canvas you need to use it.
         let _ = canvas.window().gl_set_context_to_current();
 
-        let vert_shader_src = std::fs::read_to_string(vert_shader_path).expect("shader not found!");
-        let vert_shader_id = compile_shader(&vert_shader_src, gl::VERTEX_SHADER);
-
-        let frag_shader_src = std::fs::read_to_string(frag_shader_path).expect("shader not found!");
-        let frag_shader_id = compile_shader(&frag_shader_src, gl::FRAGMENT_SHADER);
+        let shaders_modified = shaders_modified_time(&vert_shader_path, &frag_shader_path);
 
-        let program_id = link_program(vert_shader_id, frag_shader_id);
+        let program_id = match build_program(&vert_shader_path, &frag_shader_path) {
+            Ok(program_id) => program_id,
+            Err(log) => {
+                println!("{}", log);
+                panic!("Rubbish shader, rubbish programmer");
+            }
+        };
 
         let grid_size = (context.grid_width * context.grid_height) as usize;
 
         type DataType = u32;
         let pixel_data: Vec<DataType> = (0..(grid_size as DataType)).collect();
         let mem_size = std::mem::size_of::<DataType>() * grid_size;
+        let toolbar_size = (context.win_width * TOOLBAR_HEIGHT) as usize;
 
         GlslRenderer {
             vertex_array_id: GlslRenderer::load_fullscreen_vertex_buffer(),
-            grid_buffer_id: GlslRenderer::allocate_grid_buffer(mem_size),
+            grid_buffer_id: GlslRenderer::allocate_grid_buffer(mem_size, GRID_BINDING),
+            overlay_buffer_id: GlslRenderer::allocate_grid_buffer(mem_size, OVERLAY_BINDING),
+            toolbar_buffer_id: GlslRenderer::allocate_grid_buffer(toolbar_size * 4, TOOLBAR_BINDING),
             program_id: program_id,
             pixel_data: pixel_data,
+            overlay_data: vec![0; grid_size],
+            preview: Vec::new(),
+            toolbar_data: vec![0; toolbar_size],
             canvas: canvas,
+            vert_shader_path,
+            frag_shader_path,
+            shaders_modified,
+            shader_error: None,
+        }
+    }
+
+    pub fn get_shader_error(&self) -> Option<&str> {
+        self.shader_error.as_deref()
+    }
+
+    // Rebuild the program when a shader file changes on disk. If the new
+    // sources are broken, keep drawing with the old program.
+    fn reload_shaders_if_changed(&mut self) {
+        let modified = shaders_modified_time(&self.vert_shader_path, &self.frag_shader_path);
+
+        if modified == self.shaders_modified {
+            return;
+        }
+
+        self.shaders_modified = modified;
+
+        match build_program(&self.vert_shader_path, &self.frag_shader_path) {
+            Ok(program_id) => {
+                unsafe {
+                    gl::DeleteProgram(self.program_id);
+                    gl::UseProgram(program_id);
+                }
+                self.program_id = program_id;
+                self.shader_error = None;
+            },
+            Err(log) => {
+                println!("{}", log);
+                self.shader_error = Some(log);
+            }
+        }
+    }
+
+    // Match a new grid size: resize the window, viewport and grid buffer
+    pub fn resize(&mut self, context: &RenderContext) {
+        let _ = self.canvas.window_mut().set_size(context.win_width, context.win_height);
+        self.resize_viewport(context);
+
+        let grid_size = (context.grid_width * context.grid_height) as usize;
+        let mem_size = std::mem::size_of::<u32>() * grid_size;
+        self.pixel_data = vec![0; grid_size];
+        self.overlay_data = vec![0; grid_size];
+        self.preview.clear();
+
+        unsafe {
+            gl::DeleteBuffers(1, &self.grid_buffer_id);
+            gl::DeleteBuffers(1, &self.overlay_buffer_id);
+        }
+
+        self.grid_buffer_id = GlslRenderer::allocate_grid_buffer(mem_size, GRID_BINDING);
+        self.overlay_buffer_id = GlslRenderer::allocate_grid_buffer(mem_size, OVERLAY_BINDING);
+    }
+
+    // Upload the cells Physics touched last tick, for RenderMode::ChangedCells
+    pub fn set_changed_cells(&mut self, changed: &Grid<bool>) {
+        for (i, c) in changed.grid.iter().enumerate() {
+            if *c {
+                self.overlay_data[i] |= OVERLAY_CHANGED;
+            } else {
+                self.overlay_data[i] &= !OVERLAY_CHANGED;
+            }
+        }
+
+        self.upload_overlay();
+    }
+
+    // Highlight cells a tool is about to change, in the colour of the
+    // material being drawn
+    pub fn set_preview(&mut self, cells: &[(i32, i32)], context: &RenderContext) {
+        // Usually nothing has moved since last frame
+        if cells == &self.preview[..] {
+            return;
+        }
+
+        let in_grid = |&(x, y): &(i32, i32)| x >= 0 && x < context.grid_width && y >= 0 && y < context.grid_height;
+
+        for &(x, y) in self.preview.iter().filter(|cell| in_grid(cell)) {
+            self.overlay_data[(x + y * context.grid_width) as usize] &= !OVERLAY_PREVIEW;
+        }
+
+        for &(x, y) in cells.iter().filter(|cell| in_grid(cell)) {
+            self.overlay_data[(x + y * context.grid_width) as usize] |= OVERLAY_PREVIEW;
+        }
+
+        self.preview = cells.to_vec();
+        self.upload_overlay();
+    }
+
+    // Pixels from Toolbar::update, one row after another across the window
+    pub fn set_toolbar(&mut self, pixels: Vec<u32>) {
+        self.toolbar_data = pixels;
+        self.upload_toolbar();
+    }
+
+    // Skipped while the pixels are for a different window size than the
+    // buffer, the toolbar redraws for the new size soon after
+    fn upload_toolbar(&mut self) {
+        let mut buffer_size: GLint = 0;
+        let data_size = self.toolbar_data.len() * std::mem::size_of::<u32>();
+
+        unsafe {
+            gl::GetNamedBufferParameteriv(self.toolbar_buffer_id, gl::BUFFER_SIZE, &mut buffer_size);
+
+            if buffer_size as usize == data_size {
+                gl::NamedBufferSubData(
+                    self.toolbar_buffer_id,
+                    0,
+                    data_size as isize,
+                    self.toolbar_data.as_ptr() as *const std::ffi::c_void
+                );
+            }
         }
     }
 
-    fn allocate_grid_buffer(mem_size: usize) -> GLuint {
+    fn upload_overlay(&mut self) {
+        unsafe {
+            gl::NamedBufferSubData(
+                self.overlay_buffer_id,
+                0,
+                (self.overlay_data.len() * std::mem::size_of::<u32>()) as isize,
+                self.overlay_data.as_ptr() as *const std::ffi::c_void
+            );
+        }
+    }
+
+    // Match the GL viewport and toolbar buffer to a window that has changed size
+    pub fn resize_viewport(&mut self, context: &RenderContext) {
+        unsafe {
+            gl::Viewport(0, 0, context.win_width as GLint, context.win_height as GLint);
+            gl::DeleteBuffers(1, &self.toolbar_buffer_id);
+        }
+
+        let toolbar_size = (context.win_width * TOOLBAR_HEIGHT) as usize * std::mem::size_of::<u32>();
+        self.toolbar_buffer_id = GlslRenderer::allocate_grid_buffer(toolbar_size, TOOLBAR_BINDING);
+        self.upload_toolbar();
+    }
+
+    fn allocate_grid_buffer(mem_size: usize, binding: GLuint) -> GLuint {
         let mut grid_buffer_id: GLuint = 0;
 
         unsafe {
@@ -79,7 +275,7 @@ impl GlslRenderer {
                 gl::DYNAMIC_DRAW
             );
 
-            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 3, grid_buffer_id);
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, grid_buffer_id);
         }
 
         grid_buffer_id
@@ -136,6 +332,22 @@ impl GlslRenderer {
         }
     }
 
+    fn set_uniform_vec3_array(&mut self, name: &str, values: &[[f32; 3]]) {
+        let loc = self.get_uniform_location(name);
+
+        unsafe {
+            gl::Uniform3fv(loc, values.len() as GLint, values.as_ptr() as *const GLfloat);
+        }
+    }
+
+    fn set_uniform_f32_array(&mut self, name: &str, values: &[f32]) {
+        let loc = self.get_uniform_location(name);
+
+        unsafe {
+            gl::Uniform1fv(loc, values.len() as GLint, values.as_ptr());
+        }
+    }
+
     pub fn set_uniform_i32(&mut self, name: &str, value: i32) {
         let loc = self.get_uniform_location(name);
 
@@ -147,25 +359,42 @@ impl GlslRenderer {
 
 impl Renderer for GlslRenderer {
     fn render(&mut self, grid: &ParticleGrid, context: &RenderContext) {
+        self.reload_shaders_if_changed();
         self.canvas.clear();
 
         self.set_uniform_i32("grid_width", context.grid_width);
+        self.set_uniform_i32("win_width", context.win_width as i32);
         self.set_uniform_i32("win_height", context.win_height as i32);
-        self.set_uniform_f32("scale", context.scale);
+        self.set_uniform_i32("toolbar_height", TOOLBAR_HEIGHT as i32);
+        self.set_uniform_f32("scale", context.cell_size());
+        self.set_uniform_i32("view_x", context.view_x);
+        self.set_uniform_i32("view_y", context.view_y);
+
+        let (view_width, view_height) = context.view_size();
+        self.set_uniform_f32("view_width", view_width);
+        self.set_uniform_f32("view_height", view_height);
+        self.set_uniform_f32("camera_x", context.camera.x);
+        self.set_uniform_f32("camera_y", context.camera.y);
         self.set_uniform_i32("max_fill", context.max_fill as i32);
+        self.set_uniform_vec3_array("colors", &context.palette.colors);
+        self.set_uniform_f32_array("variations", &context.palette.variations);
+
+        self.set_uniform_i32("show_grid_lines", context.show_grid_lines as i32);
+        self.set_uniform_i32("render_mode", context.render_mode.uniform_id());
+        if let RenderMode::TypeMask(p_type) = context.render_mode {
+            self.set_uniform_i32("mask_type", p_type.id() as i32);
+        }
+        self.set_uniform_i32("preview_type", context.draw_type.id() as i32);
 
-        self.set_uniform_i32("mouse_x", (context.mouse_x as f32 / context.scale) as i32);
-        self.set_uniform_i32("mouse_y", (context.mouse_y as f32 / context.scale) as i32);
+        let (mouse_x, mouse_y) = context.mouse_grid_pos();
+        self.set_uniform_i32("mouse_x", mouse_x);
+        self.set_uniform_i32("mouse_y", mouse_y);
 
         for (i, p) in grid.grid.iter().enumerate() {
-            let type_id: u32 = match p.p_type {
-                ParticleType::Water => 1 << 8,
-                ParticleType::Sand  => 2 << 8,
-                ParticleType::Wood  => 3 << 8,
-                _                   => 0 << 8,
-            };
-
-            self.pixel_data[i] = type_id + p.fill_ratio as u32;
+            self.pixel_data[i] =
+                (p.shade as u32) << 16 |
+                (p.p_type.id() as u32) << 8 |
+                p.fill_ratio as u32;
         }
 
         unsafe {
@@ -199,7 +428,42 @@ impl Renderer for GlslRenderer {
     }
 }
 
-fn link_program(vert_shader_id: GLuint, frag_shader_id: GLuint) -> GLuint {
+fn shaders_modified_time(vert_shader_path: &str, frag_shader_path: &str) -> Option<SystemTime> {
+    let modified = |path: &str| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    modified(vert_shader_path).max(modified(frag_shader_path))
+}
+
+fn build_program(vert_shader_path: &str, frag_shader_path: &str) -> Result<GLuint, String> {
+    let vert_shader_id = compile_shader_file(vert_shader_path, gl::VERTEX_SHADER)?;
+
+    let frag_shader_id = match compile_shader_file(frag_shader_path, gl::FRAGMENT_SHADER) {
+        Ok(frag_shader_id) => frag_shader_id,
+        Err(log) => {
+            unsafe { gl::DeleteShader(vert_shader_id) };
+            return Err(log);
+        }
+    };
+
+    let program = link_program(vert_shader_id, frag_shader_id);
+
+    // The program keeps what it needs once linked
+    unsafe {
+        gl::DeleteShader(vert_shader_id);
+        gl::DeleteShader(frag_shader_id);
+    }
+
+    program
+}
+
+fn compile_shader_file(path: &str, ty: GLenum) -> Result<GLuint, String> {
+    let src = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read shader {}: {}", path, e))?;
+
+    compile_shader(&src, ty).map_err(|log| format!("Shader compile error in {}:\n{}", path, log))
+}
+
+fn link_program(vert_shader_id: GLuint, frag_shader_id: GLuint) -> Result<GLuint, String> {
     let program_id = unsafe { gl::CreateProgram() };
 
     let successful: bool;
@@ -217,31 +481,36 @@ fn link_program(vert_shader_id: GLuint, frag_shader_id: GLuint) -> GLuint {
     }
 
     if successful {
-        program_id
+        Ok(program_id)
     } else {
-        panic!("Failed to link the program:\n{}", get_link_log(program_id))
+        let log = get_link_log(program_id);
+        unsafe { gl::DeleteProgram(program_id) };
+        Err(format!("Failed to link the program:\n{}", log))
     }
 }
 
 fn get_link_log(program_id: GLuint) -> String {
     let mut len = 0;
     unsafe { gl::GetProgramiv(program_id, gl::INFO_LOG_LENGTH, &mut len) };
-    assert!(len > 0);
 
-    let mut buf = Vec::with_capacity(len as usize);
-    let buf_ptr = buf.as_mut_ptr() as *mut gl::types::GLchar;
+    if len <= 0 {
+        return String::new();
+    }
+
+    let mut buf = vec![0u8; len as usize];
     unsafe {
-        gl::GetProgramInfoLog(program_id, len, std::ptr::null_mut(), buf_ptr);
-        buf.set_len(len as usize);
+        gl::GetProgramInfoLog(
+            program_id,
+            len,
+            std::ptr::null_mut(),
+            buf.as_mut_ptr() as *mut GLchar
+        );
     };
 
-    match String::from_utf8(buf) {
-        Ok(log) => log,
-        Err(vec) => panic!("Could not convert link log from buffer: {}", vec)
-    }
+    String::from_utf8_lossy(&buf).trim_end_matches('\0').to_string()
 }
 
-fn compile_shader(src: &str, ty: GLenum) -> GLuint {
+fn compile_shader(src: &str, ty: GLenum) -> Result<GLuint, String> {
     let shader;
     unsafe {
         shader = gl::CreateShader(ty);
@@ -259,7 +528,7 @@ fn compile_shader(src: &str, ty: GLenum) -> GLuint {
             let mut len = 0;
             gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
 
-            let mut buf = vec![32u8; len as usize];
+            let mut buf = vec![32u8; len.max(0) as usize];
 
             gl::GetShaderInfoLog(
                 shader,
@@ -268,16 +537,12 @@ fn compile_shader(src: &str, ty: GLenum) -> GLuint {
                 buf.as_mut_ptr() as *mut GLchar,
             );
 
-            let message = String::from_utf8(buf.as_slice().to_vec())
-                .ok()
-                .expect("ShaderInfoLog not valid utf8");
-
-            println!("Shader compile error:\n{}", message);
+            gl::DeleteShader(shader);
 
-            panic!("Rubbish shader, rubbish programmer");
+            return Err(String::from_utf8_lossy(&buf).trim_end_matches('\0').to_string());
         }
     }
-    shader
+    Ok(shader)
 }
 
 fn find_sdl_gl_driver() -> Option<u32> {
@@ -1,43 +1,147 @@
 use crate::ParticleGrid;
 use crate::ParticleType;
+use crate::camera::Camera;
+use crate::palette::Palette;
+use crate::render::RenderMode;
+use crate::toolbar::TOOLBAR_HEIGHT;
+
+// Fraction of the desktop the window may take up when it is first opened
+static MAX_DISPLAY_FRACTION: f32 = 0.9;
 
 pub struct RenderContext {
     pub scale: f32,
     pub win_width: u32,
     pub win_height: u32,
+    // Top left of the area the grid is drawn in, in window pixels.
+    // Non-zero when the grid doesn't fill the window exactly (letterboxing).
+    pub view_x: i32,
+    pub view_y: i32,
     pub grid_width: i32,
     pub grid_height: i32,
     pub mouse_x: i32,
     pub mouse_y: i32,
     pub draw_type: ParticleType,
     pub max_fill: u8,
+    pub camera: Camera,
+    pub palette: Palette,
+    pub render_mode: RenderMode,
+    pub show_grid_lines: bool,
+    display_width: u32,
+    display_height: u32,
 }
 
 impl RenderContext {
-    pub fn new(grid: &ParticleGrid, max_fill: u8) -> RenderContext {
-        // FIXME: This is waste
-        // GetDesktopDisplayMode
-        let max_win_width = 2400.0;
-        let max_win_height = 1400.0;
-
-        let scale =
-            ((max_win_width / grid.width as f32)
-             .min(max_win_height / grid.height as f32))
-            .floor();
-
-        let win_width = (grid.width as f32 * scale).ceil() as u32;
-        let win_height = (grid.height as f32 * scale).ceil() as u32;
-
-        RenderContext {
-            scale: scale,
-            win_width: win_width,
-            win_height: win_height,
-            grid_width: grid.width,
-            grid_height: grid.height,
+    pub fn new(
+        grid: &ParticleGrid,
+        max_fill: u8,
+        display_width: u32,
+        display_height: u32
+    ) -> RenderContext {
+        let mut context = RenderContext {
+            scale: 1.0,
+            win_width: 0,
+            win_height: 0,
+            view_x: 0,
+            view_y: 0,
+            grid_width: 0,
+            grid_height: 0,
             mouse_x: 0,
             mouse_y: 0,
             draw_type: ParticleType::Water,
-            max_fill: max_fill,
+            max_fill,
+            camera: Camera::new(),
+            palette: Palette::default(),
+            render_mode: RenderMode::Normal,
+            show_grid_lines: false,
+            display_width,
+            display_height,
+        };
+
+        context.resize(grid);
+        context
+    }
+
+    // Recompute the scale and window size to fit the grid's dimensions
+    pub fn resize(&mut self, grid: &ParticleGrid) {
+        let max_win_width = self.display_width as f32 * MAX_DISPLAY_FRACTION;
+        let max_win_height = self.display_height as f32 * MAX_DISPLAY_FRACTION;
+
+        self.grid_width = grid.width;
+        self.grid_height = grid.height;
+
+        let scale = self.fit_scale(max_win_width, max_win_height - TOOLBAR_HEIGHT as f32);
+
+        self.camera.reset();
+        self.set_window_size(
+            (grid.width as f32 * scale).ceil() as u32,
+            (grid.height as f32 * scale).ceil() as u32 + TOOLBAR_HEIGHT
+        );
+    }
+
+    // Fit the grid into a (possibly user resized) window, below the toolbar
+    pub fn set_window_size(&mut self, win_width: u32, win_height: u32) {
+        let grid_area_height = win_height.saturating_sub(TOOLBAR_HEIGHT).max(1);
+
+        self.win_width = win_width;
+        self.win_height = win_height;
+        self.scale = self.fit_scale(win_width as f32, grid_area_height as f32);
+
+        let view_width = (self.grid_width as f32 * self.scale).floor() as i32;
+        let view_height = (self.grid_height as f32 * self.scale).floor() as i32;
+
+        self.view_x = (win_width as i32 - view_width) / 2;
+        self.view_y = TOOLBAR_HEIGHT as i32 + (grid_area_height as i32 - view_height) / 2;
+    }
+
+    // Clicks there are for the toolbar rather than the grid
+    pub fn mouse_over_toolbar(&self) -> bool {
+        self.mouse_y < TOOLBAR_HEIGHT as i32
+    }
+
+    // Largest scale at which the whole grid fits. Integer when possible so
+    // every cell is the same number of pixels wide.
+    fn fit_scale(&self, width: f32, height: f32) -> f32 {
+        let scale =
+            (width / self.grid_width as f32)
+            .min(height / self.grid_height as f32);
+
+        if scale >= 1.0 {
+            scale.floor()
+        } else {
+            scale
         }
     }
+
+    // Size of the grid on screen at zoom 1, in pixels
+    pub fn view_size(&self) -> (f32, f32) {
+        (self.grid_width as f32 * self.scale, self.grid_height as f32 * self.scale)
+    }
+
+    // Size of a single cell on screen, in pixels
+    pub fn cell_size(&self) -> f32 {
+        self.camera.cell_size(self.scale)
+    }
+
+    pub fn screen_to_grid(&self, screen_x: i32, screen_y: i32) -> (i32, i32) {
+        self.camera.screen_to_grid(self.scale, screen_x - self.view_x, screen_y - self.view_y)
+    }
+
+    pub fn mouse_grid_pos(&self) -> (i32, i32) {
+        self.screen_to_grid(self.mouse_x, self.mouse_y)
+    }
+
+    pub fn zoom_at_mouse(&mut self, factor: f32) {
+        self.camera.zoom_at(
+            factor,
+            self.scale,
+            self.mouse_x - self.view_x,
+            self.mouse_y - self.view_y,
+            self.grid_width,
+            self.grid_height
+        );
+    }
+
+    pub fn pan(&mut self, dx: i32, dy: i32) {
+        self.camera.pan(dx, dy, self.scale, self.grid_width, self.grid_height);
+    }
 }
@@ -1,9 +1,22 @@
-use crate::ParticleGrid;
+use crate::grid::{Grid, Particle, ParticleGrid, ParticleType};
 
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use serde::{Serialize, Deserialize};
 use std::io::prelude::*;
+use std::fs;
 use std::fs::File;
 use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub static SAVE_VERSION: u8 = 2;
+// Where save slots and timestamped saves go
+static SAVE_DIR: &str = "saves";
+pub static SLOT_COUNT: u8 = 9;
+// Shades given to version 1 particles come from this, so an old save always
+// upgrades to the same world
+static UPGRADE_SEED: u64 = 1;
 
 #[derive(Serialize, Deserialize)]
 pub struct SaveState {
@@ -11,26 +24,242 @@ pub struct SaveState {
     pub grid: ParticleGrid,
 }
 
+// Version 1 particles, from before they had shades
+#[derive(Clone, Serialize, Deserialize)]
+struct ParticleV1 {
+    p_type: ParticleType,
+    fill_ratio: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveStateV1 {
+    max_fill: u8,
+    grid: Grid<ParticleV1>,
+}
+
+impl SaveStateV1 {
+    fn upgrade(self) -> SaveState {
+        let mut rng = ChaCha8Rng::seed_from_u64(UPGRADE_SEED);
+
+        let particles = self.grid.grid.into_iter().map(|p| {
+            if p.p_type == ParticleType::Empty {
+                Default::default()
+            } else {
+                Particle {
+                    p_type: p.p_type,
+                    fill_ratio: p.fill_ratio,
+                    shade: rng.gen(),
+                }
+            }
+        }).collect();
+
+        SaveState {
+            max_fill: self.max_fill,
+            grid: Grid {
+                width: self.grid.width,
+                height: self.grid.height,
+                grid: particles,
+            },
+        }
+    }
+}
+
 impl SaveState {
-    pub fn load(path: String) -> SaveState {
-        let mut f = File::open(path).expect("File not found");
+    pub fn load(path: String) -> Result<SaveState, String> {
+        SaveState::load_with_version(path).map(|(state, _)| state)
+    }
+
+    // Also returns the version the file was saved with
+    pub fn load_with_version(path: String) -> Result<(SaveState, u8), String> {
+        let mut f = File::open(&path).map_err(|e| format!("Could not open {}: {}", path, e))?;
 
-        // Ignore version for now
         let mut version: [u8; 1] = [0; 1];
-        let _ = f.read(&mut version).expect("File is empty");
+        f.read_exact(&mut version).map_err(|_| format!("{} is empty", path))?;
 
         let mut buff_bois: Vec<u8> = Vec::new();
-        let _ = f.read_to_end(&mut buff_bois).unwrap();
+        f.read_to_end(&mut buff_bois).map_err(|e| format!("Could not read {}: {}", path, e))?;
+
+        let corrupted = |e| format!("{} is corrupted: {}", path, e);
+
+        let state = match version[0] {
+            1 => {
+                let state: SaveStateV1 = bincode::deserialize(&buff_bois).map_err(corrupted)?;
+                state.upgrade()
+            },
+            2 => bincode::deserialize(&buff_bois).map_err(corrupted)?,
+            v => return Err(format!("{} has unsupported save version {}", path, v)),
+        };
 
-        bincode::deserialize(&buff_bois).expect("Corrupted file")
+        // Physics and the renderer index the grid by its width and height
+        let grid = &state.grid;
+        if grid.width < 1 || grid.height < 1 || grid.grid.len() as i64 != grid.width as i64 * grid.height as i64 {
+            return Err(format!("{} is corrupted: {}x{} grid has {} cells", path, grid.width, grid.height, grid.grid.len()));
+        }
+
+        // Fill ratios are divided by it
+        if state.max_fill < 1 {
+            return Err(format!("{} is corrupted: max fill is 0", path));
+        }
+
+        Ok((state, version[0]))
+    }
+
+    // Saving over an existing file first moves it to <path>.bak
+    pub fn save(&self, path: String) -> Result<(), String> {
+        if Path::new(&path).exists() {
+            fs::copy(&path, format!("{}.bak", path))
+                .map_err(|e| format!("Could not back up {}: {}", path, e))?;
+        }
+
+        self.save_atomic(&path)
     }
 
-    pub fn save(&self, path: String) {
+    // Write to a temporary file and rename it over the target, so a crash
+    // mid-save can't leave a half written file behind
+    pub fn save_atomic(&self, path: &str) -> Result<(), String> {
         let encoded: Vec<u8> = bincode::serialize(&self).unwrap();
 
-        let mut buffer = File::create(path).expect("Could not save to path");
-        // Version byte
-        let _ = buffer.write(&[1u8]);
-        let _ = buffer.write_all(encoded.as_ref());
+        if let Some(dir) = Path::new(path).parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir).map_err(|e| format!("Could not create {:?}: {}", dir, e))?;
+            }
+        }
+
+        let tmp_path = format!("{}.tmp", path);
+        let save_err = |e: std::io::Error| format!("Could not save to {}: {}", path, e);
+
+        let mut buffer = File::create(&tmp_path).map_err(save_err)?;
+        buffer.write_all(&[SAVE_VERSION])
+            .and_then(|_| buffer.write_all(encoded.as_ref()))
+            .and_then(|_| buffer.sync_all())
+            .map_err(save_err)?;
+
+        fs::rename(&tmp_path, path).map_err(save_err)
+    }
+}
+
+// "<stem>.<extension>", or with a counter when that's taken, e.g.
+// "<stem>-2.<extension>", so files named after the second they were made in
+// don't replace each other
+pub fn unused_path(stem: &str, extension: &str) -> String {
+    let mut path = format!("{}.{}", stem, extension);
+    let mut count = 2;
+
+    while Path::new(&path).exists() {
+        path = format!("{}-{}.{}", stem, count, extension);
+        count += 1;
+    }
+
+    path
+}
+
+pub fn slot_path(slot: u8) -> String {
+    format!("{}/slot{}.mp", SAVE_DIR, slot)
+}
+
+// A new file name in the save directory, e.g. saves/save-1623456789.mp
+pub fn timestamped_path() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    save_path(SAVE_DIR, secs)
+}
+
+// Saves in the same second get a counter, e.g. saves/save-1623456789-2.mp
+fn save_path(dir: &str, secs: u64) -> String {
+    unused_path(&format!("{}/save-{}", dir, secs), "mp")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_in_the_same_second_get_their_own_names() {
+        let dir = std::env::temp_dir().join(format!("magicpixel-saves-{}", std::process::id()));
+        let dir = dir.to_string_lossy().to_string();
+        fs::create_dir_all(&dir).unwrap();
+
+        let first = save_path(&dir, 100);
+        fs::write(&first, []).unwrap();
+        let second = save_path(&dir, 100);
+        fs::write(&second, []).unwrap();
+        let third = save_path(&dir, 100);
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(first.ends_with("save-100.mp"));
+        assert!(second.ends_with("save-100-2.mp"));
+        assert!(third.ends_with("save-100-3.mp"));
+    }
+
+    #[test]
+    fn grids_that_dont_fit_their_size_are_corrupted() {
+        let path = std::env::temp_dir()
+            .join(format!("magicpixel-bad-grid-{}.mp", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+
+        let mut grid = ParticleGrid::new(4, 4);
+        grid.grid.truncate(10);
+        SaveState { max_fill: 8, grid }.save_atomic(&path).unwrap();
+
+        let loaded = SaveState::load(path.clone());
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(loaded, Err(e) if e.contains("4x4 grid has 10 cells")));
+    }
+
+    #[test]
+    fn saves_without_fill_levels_are_corrupted() {
+        let path = std::env::temp_dir()
+            .join(format!("magicpixel-no-fill-{}.mp", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+
+        SaveState { max_fill: 0, grid: ParticleGrid::new(4, 4) }.save_atomic(&path).unwrap();
+
+        let loaded = SaveState::load(path.clone());
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(loaded, Err(e) if e.contains("max fill is 0")));
+    }
+
+    #[test]
+    fn version_1_saves_upgrade_the_same_every_time() {
+        let path = std::env::temp_dir()
+            .join(format!("magicpixel-v1-{}.mp", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+
+        let grid = Grid {
+            width: 3,
+            height: 1,
+            grid: vec![
+                ParticleV1 { p_type: ParticleType::Water, fill_ratio: 5 },
+                ParticleV1 { p_type: ParticleType::Empty, fill_ratio: 0 },
+                ParticleV1 { p_type: ParticleType::Sand, fill_ratio: 8 },
+            ],
+        };
+
+        let mut contents = vec![1];
+        contents.extend(bincode::serialize(&SaveStateV1 { max_fill: 8, grid }).unwrap());
+        fs::write(&path, contents).unwrap();
+
+        let first = SaveState::load_with_version(path.clone());
+        let second = SaveState::load_with_version(path.clone());
+        fs::remove_file(&path).unwrap();
+
+        let (state, version) = first.unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(state.max_fill, 8);
+        assert_eq!((state.grid.width, state.grid.height), (3, 1));
+
+        let particles: Vec<(ParticleType, u8)> = state.grid.grid.iter().map(|p| (p.p_type, p.fill_ratio)).collect();
+        assert_eq!(particles, vec![(ParticleType::Water, 5), (ParticleType::Empty, 0), (ParticleType::Sand, 8)]);
+
+        assert_eq!(state.grid, second.unwrap().0.grid);
     }
 }
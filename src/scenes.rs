@@ -0,0 +1,109 @@
+// Canonical starting worlds, for benchmarks and tests
+
+use crate::grid::{Particle, ParticleGrid, ParticleType};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Scene {
+    Empty,
+    SandPile,
+    DamBreak,
+    FullBasin,
+    MixedSandWater,
+    Scattered,
+}
+
+impl Scene {
+    pub const ALL: [Scene; 6] = [
+        Scene::Empty,
+        Scene::SandPile,
+        Scene::DamBreak,
+        Scene::FullBasin,
+        Scene::MixedSandWater,
+        Scene::Scattered,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scene::Empty          => "empty",
+            Scene::SandPile       => "sand_pile",
+            Scene::DamBreak       => "dam_break",
+            Scene::FullBasin      => "full_basin",
+            Scene::MixedSandWater => "mixed",
+            Scene::Scattered      => "scattered",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Scene> {
+        Scene::ALL.iter().cloned().find(|s| s.name() == name)
+    }
+
+    // The same grid every time for the same size and seed
+    pub fn build(&self, width: i32, height: i32, max_fill: u8, seed: u64) -> ParticleGrid {
+        let mut grid = ParticleGrid::new(width, height);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        let particle = |rng: &mut ChaCha8Rng, p_type| Particle {
+            p_type,
+            fill_ratio: max_fill,
+            shade: rng.gen(),
+        };
+
+        match self {
+            Scene::Empty => {},
+            // A column of sand in the middle, which slumps into a pile
+            Scene::SandPile => {
+                for y in 0..height * 3 / 4 {
+                    for x in width * 3 / 8..width * 5 / 8 {
+                        grid.set(x, y, particle(&mut rng, ParticleType::Sand));
+                    }
+                }
+            },
+            // A block of water filling the left third, with nothing holding it back
+            Scene::DamBreak => {
+                for y in height / 4..height {
+                    for x in 0..width / 3 {
+                        grid.set(x, y, particle(&mut rng, ParticleType::Water));
+                    }
+                }
+            },
+            // A wooden basin filled to the brim, so the water is mostly at rest
+            Scene::FullBasin => {
+                for y in 0..height {
+                    for x in 0..width {
+                        let is_wall = x == 0 || x == width - 1 || y == height - 1;
+                        let p_type = if is_wall { ParticleType::Wood } else { ParticleType::Water };
+                        grid.set(x, y, particle(&mut rng, p_type));
+                    }
+                }
+            },
+            // Layers of sand over water, so the sand sinks through it
+            Scene::MixedSandWater => {
+                for y in height / 4..height {
+                    let p_type = if (y / 4) % 2 == 0 { ParticleType::Sand } else { ParticleType::Water };
+
+                    for x in 0..width {
+                        grid.set(x, y, particle(&mut rng, p_type));
+                    }
+                }
+            },
+            // A fifth of the cells with a random material
+            Scene::Scattered => {
+                let types = [ParticleType::Water, ParticleType::Sand, ParticleType::Wood];
+
+                for y in 0..height {
+                    for x in 0..width {
+                        if rng.gen_ratio(1, 5) {
+                            let p_type = types[rng.gen_range(0..types.len())];
+                            grid.set(x, y, particle(&mut rng, p_type));
+                        }
+                    }
+                }
+            },
+        }
+
+        grid
+    }
+}
@@ -0,0 +1,159 @@
+// Cells covered by the shape tools, from where the mouse was pressed to where
+// it is now. Cells can fall outside the grid, callers skip those.
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Shape {
+    Line,
+    Rect,
+    FilledRect,
+    // Centred on the start, through the end
+    Circle,
+    FilledCircle,
+}
+
+impl Shape {
+    pub const ALL: [Shape; 5] = [
+        Shape::Line,
+        Shape::Rect,
+        Shape::FilledRect,
+        Shape::Circle,
+        Shape::FilledCircle,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shape::Line         => "Line",
+            Shape::Rect         => "Rectangle",
+            Shape::FilledRect   => "Filled rectangle",
+            Shape::Circle       => "Circle",
+            Shape::FilledCircle => "Filled circle",
+        }
+    }
+
+    // Each cell once, in no particular order
+    pub fn cells(&self, start: (i32, i32), end: (i32, i32)) -> Vec<(i32, i32)> {
+        let mut cells = match self {
+            Shape::Line         => line(start, end),
+            Shape::Rect         => rect(start, end, false),
+            Shape::FilledRect   => rect(start, end, true),
+            Shape::Circle       => circle(start, radius(start, end), false),
+            Shape::FilledCircle => circle(start, radius(start, end), true),
+        };
+
+        cells.sort_unstable();
+        cells.dedup();
+        cells
+    }
+}
+
+// Bresenham's, so diagonal lines are one cell thick
+fn line((x0, y0): (i32, i32), (x1, y1): (i32, i32)) -> Vec<(i32, i32)> {
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let step_x = if x0 < x1 { 1 } else { -1 };
+    let step_y = if y0 < y1 { 1 } else { -1 };
+
+    let mut cells = Vec::new();
+    let (mut x, mut y) = (x0, y0);
+    let mut err = dx + dy;
+
+    loop {
+        cells.push((x, y));
+
+        if x == x1 && y == y1 {
+            return cells;
+        }
+
+        let err2 = 2 * err;
+        if err2 >= dy {
+            err += dy;
+            x += step_x;
+        }
+        if err2 <= dx {
+            err += dx;
+            y += step_y;
+        }
+    }
+}
+
+fn rect((x0, y0): (i32, i32), (x1, y1): (i32, i32), filled: bool) -> Vec<(i32, i32)> {
+    let (left, right) = (x0.min(x1), x0.max(x1));
+    let (top, bottom) = (y0.min(y1), y0.max(y1));
+
+    let mut cells = Vec::new();
+
+    for y in top..=bottom {
+        for x in left..=right {
+            if filled || x == left || x == right || y == top || y == bottom {
+                cells.push((x, y));
+            }
+        }
+    }
+
+    cells
+}
+
+fn radius((x0, y0): (i32, i32), (x1, y1): (i32, i32)) -> i32 {
+    let (dx, dy) = ((x1 - x0) as f32, (y1 - y0) as f32);
+    (dx * dx + dy * dy).sqrt().round() as i32
+}
+
+// A hollow circle is the cells of the filled one that have a neighbour
+// outside it, which keeps the outline closed and one cell thick
+fn circle((cx, cy): (i32, i32), radius: i32, filled: bool) -> Vec<(i32, i32)> {
+    // The extra half cell rounds off the flat bits at the ends of each axis
+    let limit = (radius * radius + radius) as i64;
+    let inside = |dx: i32, dy: i32| (dx as i64 * dx as i64 + dy as i64 * dy as i64) <= limit;
+
+    let mut cells = Vec::new();
+
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            let is_edge = !inside(dx - 1, dy) || !inside(dx + 1, dy) || !inside(dx, dy - 1) || !inside(dx, dy + 1);
+
+            if inside(dx, dy) && (filled || is_edge) {
+                cells.push((cx + dx, cy + dy));
+            }
+        }
+    }
+
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_join_their_ends() {
+        for &end in [(5, 0), (0, -5), (4, 3), (-7, 2), (0, 0)].iter() {
+            let cells = Shape::Line.cells((0, 0), end);
+
+            assert!(cells.contains(&(0, 0)) && cells.contains(&end));
+            // One cell per step along the longer axis
+            assert_eq!(cells.len() as i32, end.0.abs().max(end.1.abs()) + 1);
+        }
+    }
+
+    #[test]
+    fn rects_from_any_corner() {
+        assert_eq!(Shape::Rect.cells((3, 4), (0, 0)), Shape::Rect.cells((0, 0), (3, 4)));
+        assert_eq!(Shape::Rect.cells((0, 0), (3, 4)).len(), 14);
+        assert_eq!(Shape::FilledRect.cells((0, 0), (3, 4)).len(), 20);
+        assert_eq!(Shape::Rect.cells((2, 2), (2, 2)), vec![(2, 2)]);
+    }
+
+    #[test]
+    fn circles_are_round() {
+        let filled = Shape::FilledCircle.cells((0, 0), (5, 0));
+        let hollow = Shape::Circle.cells((0, 0), (0, -5));
+
+        for &cell in [(5, 0), (-5, 0), (0, 5), (0, -5)].iter() {
+            assert!(filled.contains(&cell) && hollow.contains(&cell));
+        }
+
+        assert!(hollow.iter().all(|cell| filled.contains(cell)));
+        assert!(!hollow.contains(&(0, 0)));
+        assert_eq!(Shape::Circle.cells((1, 1), (1, 1)), vec![(1, 1)]);
+    }
+}
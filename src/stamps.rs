@@ -0,0 +1,113 @@
+// A library of reusable structures, each a small save file in the stamp
+// directory. Any save copied there works as a stamp too.
+
+use crate::grid::{Particle, ParticleGrid, ParticleType};
+use crate::save_state::{self, SaveState};
+
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static STAMP_DIR: &str = "stamps";
+static STAMP_EXTENSION: &str = "mp";
+
+// Saves under a new name, e.g. stamps/stamp-1623456789.mp, and returns it
+pub fn save(stamp: &SaveState) -> Result<String, String> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let path = unused_path(STAMP_DIR, secs);
+    stamp.save(path.clone())?;
+
+    Ok(path)
+}
+
+// Stamps saved within the same second get a counter, e.g.
+// stamps/stamp-1623456789-2.mp
+fn unused_path(dir: &str, secs: u64) -> String {
+    save_state::unused_path(&format!("{}/stamp-{}", dir, secs), STAMP_EXTENSION)
+}
+
+// Paths of every stamp, sorted so they cycle in a stable order
+pub fn list() -> Vec<String> {
+    let entries = match fs::read_dir(STAMP_DIR) {
+        Ok(entries) => entries,
+        Err(_)      => return Vec::new(),
+    };
+
+    let mut paths: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension() == Some(std::ffi::OsStr::new(STAMP_EXTENSION)))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+
+    paths.sort();
+    paths
+}
+
+// The stamp's particles with fill ratios scaled to a world's max fill, so
+// stamps can go into worlds made with a different one
+pub fn rescale_fill(grid: &ParticleGrid, from_max_fill: u8, to_max_fill: u8) -> ParticleGrid {
+    let mut rescaled = grid.clone();
+
+    if from_max_fill == to_max_fill {
+        return rescaled;
+    }
+
+    for p in rescaled.grid.iter_mut() {
+        if p.p_type != ParticleType::Empty {
+            let fill = (p.fill_ratio as u32 * to_max_fill as u32 + from_max_fill as u32 / 2) / from_max_fill as u32;
+
+            *p = Particle {
+                fill_ratio: fill.clamp(1, to_max_fill as u32) as u8,
+                ..p.clone()
+            };
+        }
+    }
+
+    rescaled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rescaling_keeps_particles() {
+        let mut grid = ParticleGrid::new(3, 1);
+        grid.set(0, 0, Particle::new(ParticleType::Water, 8));
+        grid.set(1, 0, Particle::new(ParticleType::Water, 1));
+
+        let rescaled = rescale_fill(&grid, 8, 64);
+        assert_eq!(rescaled.get(0, 0).fill_ratio, 64);
+        assert_eq!(rescaled.get(1, 0).fill_ratio, 8);
+        assert_eq!(rescaled.get(2, 0).p_type, ParticleType::Empty);
+
+        // Nearly empty water doesn't vanish when there are fewer levels
+        let rescaled = rescale_fill(&grid, 8, 2);
+        assert_eq!(rescaled.get(0, 0).fill_ratio, 2);
+        assert_eq!(rescaled.get(1, 0).fill_ratio, 1);
+        assert_eq!(rescaled.get(1, 0).shade, grid.get(1, 0).shade);
+    }
+
+    #[test]
+    fn stamps_in_the_same_second_get_their_own_names() {
+        let dir = std::env::temp_dir().join(format!("magicpixel-stamps-{}", std::process::id()));
+        let dir = dir.to_string_lossy().to_string();
+        fs::create_dir_all(&dir).unwrap();
+
+        let first = unused_path(&dir, 100);
+        fs::write(&first, []).unwrap();
+        let second = unused_path(&dir, 100);
+        fs::write(&second, []).unwrap();
+        let third = unused_path(&dir, 100);
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(first.ends_with("stamp-100.mp"));
+        assert!(second.ends_with("stamp-100-2.mp"));
+        assert!(third.ends_with("stamp-100-3.mp"));
+    }
+}
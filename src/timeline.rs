@@ -0,0 +1,316 @@
+use crate::grid::{Particle, ParticleGrid};
+
+use std::collections::VecDeque;
+
+// Full copies of the grid are kept this many ticks apart
+static SNAPSHOT_INTERVAL: u64 = 50;
+
+struct Snapshot {
+    tick: u64,
+    grid: ParticleGrid,
+}
+
+// Cells that changed between the previous tick and this one, as
+// (index, new particle). Later entries win.
+struct Delta {
+    tick: u64,
+    changes: Vec<(usize, Particle)>,
+}
+
+// Recent simulation history, for scrubbing back and forth while paused.
+// Any tick can be rebuilt from the snapshot before it plus the deltas since.
+pub struct Timeline {
+    capacity: u64,
+    snapshots: VecDeque<Snapshot>,
+    deltas: VecDeque<Delta>,
+    // The grid as of the newest tick
+    head: ParticleGrid,
+    latest: u64,
+    // Cells edited since the newest tick, by index
+    edited: Vec<usize>,
+}
+
+impl Timeline {
+    // Keeps roughly `capacity` ticks of history, or none if it's zero
+    pub fn new(grid: &ParticleGrid, tick: u64, capacity: u64) -> Timeline {
+        let mut timeline = Timeline {
+            capacity,
+            snapshots: VecDeque::new(),
+            deltas: VecDeque::new(),
+            head: grid.clone(),
+            latest: tick,
+            edited: Vec::new(),
+        };
+
+        timeline.reset(grid, tick);
+        timeline
+    }
+
+    // Forget everything, e.g. when the world is resized or loaded
+    pub fn reset(&mut self, grid: &ParticleGrid, tick: u64) {
+        self.snapshots.clear();
+        self.deltas.clear();
+        self.head = grid.clone();
+        self.latest = tick;
+        self.edited.clear();
+
+        if self.capacity > 0 {
+            self.snapshots.push_back(Snapshot { tick, grid: grid.clone() });
+        }
+    }
+
+    pub fn earliest(&self) -> u64 {
+        self.snapshots.front().map_or(self.latest, |s| s.tick)
+    }
+
+    pub fn latest(&self) -> u64 {
+        self.latest
+    }
+
+    // Note a cell changed outside of physics, e.g. painted, so the next tick
+    // recorded picks it up
+    pub fn edit(&mut self, x: i32, y: i32) {
+        if self.capacity > 0 && self.head.in_bounds(x, y) {
+            self.edited.push((x + y * self.head.width) as usize);
+        }
+    }
+
+    // Record the grid after a physics tick, given the cells the tick wrote
+    // to. Recording a tick we already have means the user went back in
+    // time, so everything after it is dropped.
+    pub fn record(&mut self, grid: &ParticleGrid, written: &[usize], tick: u64) {
+        if self.capacity == 0 {
+            self.latest = tick;
+            return;
+        }
+
+        if tick <= self.latest || !self.is_same_size(grid) {
+            if tick > self.earliest() && self.is_same_size(grid) {
+                self.truncate(tick - 1);
+            } else {
+                self.reset(grid, tick);
+                return;
+            }
+        }
+
+        // Only written and edited cells can differ from the head
+        let mut changes = Vec::new();
+
+        for &index in written.iter().chain(self.edited.iter()) {
+            if self.head.grid[index] != grid.grid[index] {
+                self.head.grid[index] = grid.grid[index].clone();
+                changes.push((index, grid.grid[index].clone()));
+            }
+        }
+
+        self.deltas.push_back(Delta { tick, changes });
+        self.edited.clear();
+        self.latest = tick;
+
+        if tick >= self.snapshots.back().map_or(0, |s| s.tick) + SNAPSHOT_INTERVAL {
+            self.snapshots.push_back(Snapshot { tick, grid: grid.clone() });
+        }
+
+        self.trim();
+    }
+
+    // Fold edits made since the newest tick (e.g. painting while paused) into
+    // it, so they aren't lost when scrubbing away and back
+    pub fn amend(&mut self, grid: &ParticleGrid) {
+        if self.capacity == 0 || !self.is_same_size(grid) {
+            return;
+        }
+
+        let changes = self.changes_to(grid);
+
+        if changes.is_empty() {
+            return;
+        }
+
+        let latest = self.latest;
+
+        if let Some(snapshot) = self.snapshots.back_mut().filter(|s| s.tick == latest) {
+            snapshot.grid = grid.clone();
+        }
+
+        if let Some(delta) = self.deltas.back_mut().filter(|d| d.tick == latest) {
+            delta.changes.extend(changes);
+        }
+
+        self.head = grid.clone();
+        self.edited.clear();
+    }
+
+    // The grid as it was at `tick`, if that's still in the history
+    pub fn grid_at(&self, tick: u64) -> Option<ParticleGrid> {
+        if tick > self.latest {
+            return None;
+        }
+
+        let snapshot = self.snapshots.iter().rev().find(|s| s.tick <= tick)?;
+        let mut grid = snapshot.grid.clone();
+
+        for delta in self.deltas.iter().filter(|d| d.tick > snapshot.tick && d.tick <= tick) {
+            for (index, particle) in &delta.changes {
+                grid.grid[*index] = particle.clone();
+            }
+        }
+
+        Some(grid)
+    }
+
+    fn truncate(&mut self, tick: u64) {
+        if let Some(grid) = self.grid_at(tick) {
+            self.snapshots.retain(|s| s.tick <= tick);
+            self.deltas.retain(|d| d.tick <= tick);
+            self.head = grid;
+            self.latest = tick;
+        }
+    }
+
+    // Drop snapshots (and the deltas that build on them) once the one after
+    // is old enough to cover the whole history on its own
+    fn trim(&mut self) {
+        let oldest_wanted = self.latest.saturating_sub(self.capacity);
+
+        while self.snapshots.len() > 1 && self.snapshots[1].tick <= oldest_wanted {
+            self.snapshots.pop_front();
+        }
+
+        let earliest = self.earliest();
+
+        while matches!(self.deltas.front(), Some(d) if d.tick <= earliest) {
+            self.deltas.pop_front();
+        }
+    }
+
+    fn changes_to(&self, grid: &ParticleGrid) -> Vec<(usize, Particle)> {
+        self.head.grid.iter()
+            .zip(grid.grid.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(index, (_, new))| (index, new.clone()))
+            .collect()
+    }
+
+    fn is_same_size(&self, grid: &ParticleGrid) -> bool {
+        self.head.width == grid.width && self.head.height == grid.height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::ParticleType;
+    use crate::physics::Physics;
+
+    fn particle(p_type: ParticleType) -> Particle {
+        Particle { p_type, fill_ratio: 8, shade: 0 }
+    }
+
+    fn grid_with_sand_at(x: i32) -> ParticleGrid {
+        let mut grid = ParticleGrid::new(10, 1);
+        grid.set(x, 0, particle(ParticleType::Sand));
+        grid
+    }
+
+    // As if physics wrote to every cell
+    static ALL_CELLS: [usize; 10] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+
+    #[test]
+    fn rebuilds_every_tick_in_the_history() {
+        let mut timeline = Timeline::new(&grid_with_sand_at(0), 0, 1000);
+        let grids: Vec<ParticleGrid> = (0..10).map(grid_with_sand_at).collect();
+
+        for tick in 1..=120 {
+            timeline.record(&grids[tick as usize % 10], &ALL_CELLS, tick);
+        }
+
+        for tick in 1..=120 {
+            assert_eq!(timeline.grid_at(tick), Some(grids[tick as usize % 10].clone()));
+        }
+        assert_eq!(timeline.grid_at(121), None);
+    }
+
+    #[test]
+    fn old_history_is_dropped() {
+        let mut timeline = Timeline::new(&grid_with_sand_at(0), 0, 100);
+
+        for tick in 1..=500 {
+            timeline.record(&grid_with_sand_at(tick as i32 % 10), &ALL_CELLS, tick);
+        }
+
+        assert!(timeline.earliest() <= 400);
+        assert!(timeline.earliest() > 300);
+        assert_eq!(timeline.grid_at(450), Some(grid_with_sand_at(0)));
+        assert_eq!(timeline.grid_at(timeline.earliest() - 1), None);
+    }
+
+    #[test]
+    fn recording_an_old_tick_discards_the_future() {
+        let mut timeline = Timeline::new(&grid_with_sand_at(0), 0, 1000);
+
+        for tick in 1..=80 {
+            timeline.record(&grid_with_sand_at(tick as i32 % 10), &ALL_CELLS, tick);
+        }
+
+        timeline.record(&grid_with_sand_at(9), &ALL_CELLS, 31);
+
+        assert_eq!(timeline.latest(), 31);
+        assert_eq!(timeline.grid_at(30), Some(grid_with_sand_at(0)));
+        assert_eq!(timeline.grid_at(31), Some(grid_with_sand_at(9)));
+        assert_eq!(timeline.grid_at(60), None);
+    }
+
+    #[test]
+    fn amended_edits_survive_scrubbing() {
+        let mut timeline = Timeline::new(&grid_with_sand_at(0), 0, 1000);
+        timeline.record(&grid_with_sand_at(1), &ALL_CELLS, 1);
+
+        let mut edited = grid_with_sand_at(1);
+        edited.set(5, 0, particle(ParticleType::Wood));
+        timeline.amend(&edited);
+
+        assert_eq!(timeline.grid_at(1), Some(edited));
+    }
+
+    #[test]
+    fn edits_are_recorded_with_the_next_tick() {
+        let mut timeline = Timeline::new(&grid_with_sand_at(0), 0, 1000);
+
+        // The tick moves the sand, and wood is painted in before it
+        let mut next = grid_with_sand_at(1);
+        next.set(5, 0, particle(ParticleType::Wood));
+        timeline.edit(5, 0);
+        timeline.record(&next, &[0, 1], 1);
+
+        assert_eq!(timeline.grid_at(0), Some(grid_with_sand_at(0)));
+        assert_eq!(timeline.grid_at(1), Some(next));
+    }
+
+    #[test]
+    fn follows_what_physics_writes() {
+        let mut grid = ParticleGrid::new(30, 30);
+        for x in 5..25 {
+            grid.set(x, 0, particle(ParticleType::Sand));
+            grid.set(x, 5, particle(ParticleType::Water));
+            grid.set(x, 10, Particle { fill_ratio: 3, ..particle(ParticleType::Water) });
+        }
+        grid.set(12, 20, particle(ParticleType::Wood));
+
+        let mut physics = Physics::with_seed(grid, 8, 1);
+        let mut timeline = Timeline::new(physics.get_grid(), 0, 1000);
+        let mut grids = vec![physics.get_grid().as_ref().clone()];
+
+        for tick in 1..=100 {
+            physics.update();
+            let written = physics.get_written_last_tick().to_vec();
+            timeline.record(physics.get_grid(), &written, tick);
+            grids.push(physics.get_grid().as_ref().clone());
+        }
+
+        for (tick, grid) in grids.into_iter().enumerate() {
+            assert_eq!(timeline.grid_at(tick as u64), Some(grid));
+        }
+    }
+}
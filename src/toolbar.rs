@@ -0,0 +1,181 @@
+// The strip along the top of the main window with the material palette, the
+// current tool, the brush size and the simulation speed. It's drawn into a
+// pixel buffer here and the renderer puts it on screen.
+
+use crate::grid::ParticleType;
+use crate::palette::Rgb;
+use crate::tools::Tool;
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::surface::Surface;
+use sdl2::ttf::Font;
+
+// Height of the strip, in window pixels. The grid is drawn below it.
+pub static TOOLBAR_HEIGHT: u32 = 32;
+
+static FONT_SIZE: u16 = 16;
+static SWATCH_SIZE: u32 = 18;
+// Space around each item
+static PADDING: i32 = 8;
+
+static BACKGROUND_COLOR: Color = Color::RGB(30, 30, 30);
+static BUTTON_COLOR: Color = Color::RGB(70, 70, 70);
+static TEXT_COLOR: Color = Color::RGB(220, 220, 220);
+static SELECTED_COLOR: Color = Color::RGB(255, 200, 0);
+
+// What a click on the toolbar does
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ToolbarItem {
+    // Index into the materials shown
+    Material(usize),
+    NextTool,
+    BrushSmaller,
+    BrushBigger,
+    Slower,
+    Faster,
+}
+
+// Everything the toolbar shows, so it's only redrawn when some of it changes
+#[derive(Clone, PartialEq)]
+pub struct ToolbarState {
+    pub materials: Vec<ParticleType>,
+    pub selected: usize,
+    pub colors: [Rgb; 4],
+    pub tool: Tool,
+    pub brush_size: i32,
+    // Simulation speed, e.g. "2x"
+    pub speed: String,
+    // Of the window
+    pub width: u32,
+}
+
+pub struct Toolbar<'a> {
+    font: Font<'a, 'a>,
+    // Clickable areas as last drawn, in window pixels
+    items: Vec<(Rect, ToolbarItem)>,
+    drawn: Option<ToolbarState>,
+}
+
+impl <'a> Toolbar<'a> {
+    pub fn new(ttf_context: &'a sdl2::ttf::Sdl2TtfContext) -> Result<Toolbar<'a>, String> {
+        let font = ttf_context.load_font("assets/FiraCode-Light.ttf", FONT_SIZE)?;
+
+        Ok(Toolbar {
+            font,
+            items: Vec::new(),
+            drawn: None,
+        })
+    }
+
+    pub fn item_at(&self, x: i32, y: i32) -> Option<ToolbarItem> {
+        self.items.iter()
+            .find(|(rect, _)| rect.contains_point((x, y)))
+            .map(|(_, item)| *item)
+    }
+
+    // Pixels of the toolbar for `state`, packed as in grid.frag, if they
+    // differ from the last ones returned
+    pub fn update(&mut self, state: ToolbarState) -> Result<Option<Vec<u32>>, String> {
+        if self.drawn.as_ref() == Some(&state) {
+            return Ok(None);
+        }
+
+        let mut surface = Surface::new(state.width.max(1), TOOLBAR_HEIGHT, PixelFormatEnum::RGBA32)?;
+        surface.fill_rect(None, BACKGROUND_COLOR)?;
+
+        self.items.clear();
+        let mut x = PADDING;
+
+        for (i, p_type) in state.materials.iter().enumerate() {
+            let start_x = x;
+            let is_selected = i == state.selected;
+            let swatch_y = (TOOLBAR_HEIGHT - SWATCH_SIZE) as i32 / 2;
+
+            if is_selected {
+                let outline = Rect::new(x - 2, swatch_y - 2, SWATCH_SIZE + 4, SWATCH_SIZE + 4);
+                surface.fill_rect(outline, SELECTED_COLOR)?;
+            }
+
+            let [r, g, b] = state.colors[p_type.id()];
+            let swatch_color = Color::RGB((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8);
+            surface.fill_rect(Rect::new(x, swatch_y, SWATCH_SIZE, SWATCH_SIZE), swatch_color)?;
+            x += SWATCH_SIZE as i32 + PADDING / 2;
+
+            let color = if is_selected { SELECTED_COLOR } else { TEXT_COLOR };
+            x = self.draw_text(&mut surface, &format!("{:?}", p_type), x, color)?;
+
+            self.add_item(start_x, x, ToolbarItem::Material(i));
+            x += PADDING * 2;
+        }
+
+        let start_x = x;
+        x = self.draw_text(&mut surface, &format!("Tool: {}", state.tool.name()), x, TEXT_COLOR)?;
+        self.add_item(start_x, x, ToolbarItem::NextTool);
+        x += PADDING * 2;
+
+        x = self.draw_text(&mut surface, &format!("Brush: {}", state.brush_size), x, TEXT_COLOR)?;
+        x += PADDING;
+        x = self.draw_button(&mut surface, "-", x, ToolbarItem::BrushSmaller)?;
+        x += PADDING / 2;
+        x = self.draw_button(&mut surface, "+", x, ToolbarItem::BrushBigger)?;
+        x += PADDING * 2;
+
+        x = self.draw_text(&mut surface, &format!("Speed: {}", state.speed), x, TEXT_COLOR)?;
+        x += PADDING;
+        x = self.draw_button(&mut surface, "-", x, ToolbarItem::Slower)?;
+        x += PADDING / 2;
+        self.draw_button(&mut surface, "+", x, ToolbarItem::Faster)?;
+
+        self.drawn = Some(state);
+
+        Ok(Some(pack_pixels(&surface)))
+    }
+
+    // Draws text vertically centred at x, and returns where it ends
+    fn draw_text(&self, surface: &mut Surface, text: &str, x: i32, color: Color) -> Result<i32, String> {
+        let rendered = self.font.render(text).blended(color).map_err(|e| e.to_string())?;
+        let (width, height) = (rendered.width(), rendered.height());
+        let y = (TOOLBAR_HEIGHT as i32 - height as i32) / 2;
+
+        rendered.blit(None, surface, Rect::new(x, y, width, height))?;
+
+        Ok(x + width as i32)
+    }
+
+    fn draw_button(&mut self, surface: &mut Surface, label: &str, x: i32, item: ToolbarItem) -> Result<i32, String> {
+        let (label_width, _) = self.font.size_of(label).map_err(|e| e.to_string())?;
+        let width = label_width as i32 + PADDING;
+        let y = PADDING / 2;
+
+        surface.fill_rect(Rect::new(x, y, width as u32, TOOLBAR_HEIGHT - PADDING as u32), BUTTON_COLOR)?;
+        self.draw_text(surface, label, x + PADDING / 2, TEXT_COLOR)?;
+        self.add_item(x, x + width, item);
+
+        Ok(x + width)
+    }
+
+    // Clicks anywhere in the strip's height between the two x's pick the item
+    fn add_item(&mut self, start_x: i32, end_x: i32, item: ToolbarItem) {
+        let rect = Rect::new(start_x, 0, (end_x - start_x).max(1) as u32, TOOLBAR_HEIGHT);
+        self.items.push((rect, item));
+    }
+}
+
+// One u32 per pixel, red in the lowest byte
+fn pack_pixels(surface: &Surface) -> Vec<u32> {
+    let (width, height, pitch) = (surface.width() as usize, surface.height() as usize, surface.pitch() as usize);
+
+    surface.with_lock(|bytes| {
+        let mut pixels = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * pitch + x * 4;
+                pixels.push(u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]));
+            }
+        }
+
+        pixels
+    })
+}
@@ -0,0 +1,47 @@
+use crate::shapes::Shape;
+
+// What a left click in the world does
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Tool {
+    // Paints the cell under the mouse while the button is held
+    Brush,
+    // Fills the region under the mouse with one click
+    Bucket,
+    // Drawn from where the button is pressed to where it's released
+    Shape(Shape),
+    // Drags out the rectangle copy and cut work on
+    Select,
+    // Places the clipboard centred on the mouse. Picked by pasting rather
+    // than by cycling through the tools.
+    Paste,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 8] = [
+        Tool::Brush,
+        Tool::Bucket,
+        Tool::Shape(Shape::Line),
+        Tool::Shape(Shape::Rect),
+        Tool::Shape(Shape::FilledRect),
+        Tool::Shape(Shape::Circle),
+        Tool::Shape(Shape::FilledCircle),
+        Tool::Select,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Brush        => "Brush",
+            Tool::Bucket       => "Bucket",
+            Tool::Shape(shape) => shape.name(),
+            Tool::Select       => "Select",
+            Tool::Paste        => "Paste",
+        }
+    }
+
+    pub fn next(&self) -> Tool {
+        match Tool::ALL.iter().position(|t| t == self) {
+            Some(i) => Tool::ALL[(i + 1) % Tool::ALL.len()],
+            None    => Tool::Brush,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+// Golden snapshot tests for physics behaviour. Each scenario runs a small,
+// hand drawn world for a fixed number of ticks with a fixed seed, and compares
+// the result against tests/snapshots/<name>.txt.
+//
+// When behaviour changes on purpose, re-bless the snapshots with
+//
+//     BLESS=1 cargo test --test physics_snapshots
+//
+// and check the diff of tests/snapshots before committing it.
+
+use magicpixel::grid::{Particle, ParticleGrid, ParticleType};
+use magicpixel::physics::Physics;
+
+use std::fs;
+use std::path::PathBuf;
+
+static MAX_FILL: u8 = 8;
+static SEED: u64 = 1;
+
+// Cells are drawn as '.' empty, 'S' sand, '#' wood, and 'W' for full water,
+// or the digits 1 to 7 for partly filled water
+fn parse(rows: &[&str]) -> ParticleGrid {
+    let mut grid = ParticleGrid::new(rows[0].len() as i32, rows.len() as i32);
+
+    for (y, row) in rows.iter().enumerate() {
+        assert_eq!(row.len(), grid.width as usize, "Row {} is a different width", y);
+
+        for (x, ch) in row.chars().enumerate() {
+            let (p_type, fill_ratio) = match ch {
+                '.' => (ParticleType::Empty, 0),
+                'S' => (ParticleType::Sand, MAX_FILL),
+                '#' => (ParticleType::Wood, MAX_FILL),
+                'W' => (ParticleType::Water, MAX_FILL),
+                '1'..='7' => (ParticleType::Water, ch.to_digit(10).unwrap() as u8),
+                _ => panic!("Unknown cell '{}'", ch),
+            };
+
+            grid.set(x as i32, y as i32, Particle { p_type, fill_ratio, shade: 0 });
+        }
+    }
+
+    grid
+}
+
+fn draw(grid: &ParticleGrid) -> String {
+    let mut out = String::new();
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let p = grid.get(x, y);
+
+            out.push(match p.p_type {
+                ParticleType::Empty => '.',
+                ParticleType::Sand  => 'S',
+                ParticleType::Wood  => '#',
+                ParticleType::Water if p.fill_ratio >= MAX_FILL => 'W',
+                ParticleType::Water => std::char::from_digit(p.fill_ratio as u32, 10).unwrap_or('?'),
+            });
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+fn check_snapshot(name: &str, rows: &[&str], ticks: u64) {
+    let mut physics = Physics::with_seed(parse(rows), MAX_FILL, SEED);
+
+    for _ in 0..ticks {
+        physics.update();
+    }
+
+    let actual = format!("# {} after {} ticks\n{}", name, ticks, draw(physics.get_grid()));
+
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "snapshots", &format!("{}.txt", name)]
+        .iter()
+        .collect();
+
+    if std::env::var_os("BLESS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!("No snapshot at {:?}, run with BLESS=1 to create it", path)
+    });
+
+    assert!(
+        actual == expected,
+        "{} doesn't match its snapshot. Run with BLESS=1 if that's intended.\n\nexpected:\n{}\nactual:\n{}",
+        name,
+        expected,
+        actual
+    );
+}
+
+#[test]
+fn sand_falls() {
+    check_snapshot("sand_falls", &[
+        "..S..",
+        ".SSS.",
+        ".....",
+        ".....",
+        ".....",
+        ".....",
+        ".....",
+        "#####",
+    ], 10);
+}
+
+#[test]
+fn sand_sinks_through_water() {
+    check_snapshot("sand_sinks_through_water", &[
+        "#.SSS.#",
+        "#.SSS.#",
+        "#WWWWW#",
+        "#WWWWW#",
+        "#WWWWW#",
+        "#WWWWW#",
+        "#######",
+    ], 30);
+}
+
+#[test]
+fn water_levels_in_u_tube() {
+    check_snapshot("water_levels_in_u_tube", &[
+        "#W#...#",
+        "#W#...#",
+        "#W#...#",
+        "#W#...#",
+        "#W#...#",
+        "#W....#",
+        "#WW...#",
+        "#######",
+    ], 200);
+}
+
+#[test]
+fn water_fills_container() {
+    check_snapshot("water_fills_container", &[
+        ".#.WWW.#.",
+        ".#.WWW.#.",
+        ".#.....#.",
+        ".#.....#.",
+        ".#.....#.",
+        ".#######.",
+        ".........",
+    ], 100);
+}
+
+#[test]
+fn same_seed_same_result() {
+    let rows = [
+        "..WWWS..",
+        "..SWWW..",
+        "........",
+        "#......#",
+        "########",
+    ];
+
+    let run = || {
+        let mut physics = Physics::with_seed(parse(&rows), MAX_FILL, SEED);
+        for _ in 0..50 {
+            physics.update();
+        }
+        draw(physics.get_grid())
+    };
+
+    assert_eq!(run(), run());
+}